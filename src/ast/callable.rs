@@ -0,0 +1,136 @@
+use anyhow::Result;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Formatter};
+
+use super::{
+    environment::Environment,
+    fps::{Resume, Suspended},
+    literal::LiteralValue,
+    runtime::{Flow, Runtime},
+    statement::Statement,
+    AstError,
+};
+use crate::lexer::Token;
+
+/// user function calls that can be nested, deeper recursion would overflow the stack
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// User defined function declared with `fn`
+pub struct Function {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Statement>,
+    /// scope where the function was declared, shared with it so the function can see later assignments and itself
+    pub closure: Environment,
+}
+
+impl Debug for Function {
+    fn fmt(&self, format: &mut Formatter) -> fmt::Result {
+        write!(format, "<fn {}>", self.name.lexeme)
+    }
+}
+
+// functions are only equal to themselves
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for Function {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self == other {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+}
+
+impl Function {
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    /// call from an expression, its value is needed right away so the body can't `wait`
+    pub fn call(&self, runtime: &mut dyn Runtime, arguments: Vec<LiteralValue>, paren: &Token) -> Result<LiteralValue> {
+        match self.run(runtime, arguments, paren)? {
+            Flow::Return(value) => Ok(value),
+            Flow::Next => Ok(LiteralValue::Null),
+            Flow::Wait { .. } => Err(AstError::WaitInExpression(self.name.lexeme.clone(), paren.line).into()),
+        }
+    }
+
+    /// run the body, a `wait` suspends it together with the blocks the function was called from
+    pub fn run(&self, runtime: &mut dyn Runtime, arguments: Vec<LiteralValue>, paren: &Token) -> Result<Flow> {
+        if arguments.len() != self.arity() {
+            return Err(AstError::Arity(self.name.lexeme.clone(), self.arity(), arguments.len(), paren.line).into());
+        }
+
+        if runtime.call_depth() >= MAX_CALL_DEPTH {
+            return Err(AstError::CallDepth(MAX_CALL_DEPTH).into());
+        }
+
+        let environment = self.closure.child();
+
+        for (param, argument) in self.params.iter().zip(arguments) {
+            environment.declare(param.lexeme.clone(), argument)?;
+        }
+
+        runtime.enter_call();
+        let flow = runtime.execute_block(self.body.clone(), environment.clone());
+        runtime.exit_call();
+
+        match flow? {
+            Flow::Wait { frames, mut suspended } => {
+                suspended.push(Suspended {
+                    kind: Resume::Call,
+                    environment,
+                    iterations: vec![],
+                    span: 1,
+                });
+                Ok(Flow::Wait { frames, suspended })
+            }
+            flow => Ok(flow),
+        }
+    }
+}
+
+/// Function provided by the interpreter, like the string built-ins, the runtime runs the built-in of its name
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, format: &mut Formatter) -> fmt::Result {
+        write!(format, "<native fn {}>", self.name)
+    }
+}
+
+// there is a single native function per name
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl PartialOrd for NativeFunction {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self == other {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+}
+
+impl NativeFunction {
+    pub fn call(&self, runtime: &mut dyn Runtime, arguments: Vec<LiteralValue>, paren: &Token) -> Result<LiteralValue> {
+        if arguments.len() != self.arity {
+            return Err(AstError::Arity(self.name.to_owned(), self.arity, arguments.len(), paren.line).into());
+        }
+
+        runtime.call_native(self, arguments)
+    }
+}
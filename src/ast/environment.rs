@@ -1,5 +1,9 @@
 use anyhow::Result;
-use std::{collections::BTreeMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
 use thiserror::Error;
 
 use super::literal::LiteralValue;
@@ -10,6 +14,8 @@ enum AstError {
     AlreadyDeclared(String),
     #[error("Variable '{0}' is not yet declared in current scope")]
     NotDeclared(String),
+    #[error("Cannot assign to built-in '{0}'")]
+    ReadOnly(String),
 }
 
 // #[derive(Debug)]
@@ -18,56 +24,104 @@ enum AstError {
 //     pub value: LiteralValue,
 // }
 
-#[derive(Debug, Clone)]
-pub struct Environment {
-    pub parent: Option<Rc<Environment>>,
+#[derive(Debug)]
+struct Scope {
+    parent: Option<Environment>,
     variables: BTreeMap<String, LiteralValue>,
+    /// variables of the scope can't be assigned, e.g. built-ins
+    read_only: bool,
 }
 
+/// Handle to a scope, clones share the same scope so assignments are seen through every handle
+#[derive(Debug, Clone)]
+pub struct Environment(Rc<RefCell<Scope>>);
+
 impl Environment {
     pub fn new() -> Self {
-        Self {
+        Self(Rc::new(RefCell::new(Scope {
             parent: None,
             variables: BTreeMap::new(),
-        }
+            read_only: false,
+        })))
     }
 
-    fn resolve(&mut self, name: String) -> Result<&mut Self> {
-        if self.variables.contains_key(&name) {
-            return Ok(self);
-        }
-
-        if let Some(parent) = &mut self.parent {
-            return Rc::get_mut(parent).expect("Could not get mutable reference to environment").resolve(name);
-        } else {
-            return Err(AstError::NotDeclared(name).into());
-        }
+    /// new inner scope that resolves undeclared variables through this one
+    pub fn child(&self) -> Self {
+        Self(Rc::new(RefCell::new(Scope {
+            parent: Some(self.clone()),
+            variables: BTreeMap::new(),
+            read_only: false,
+        })))
     }
 
-    pub fn get(&mut self, name: String) -> Result<LiteralValue> {
-        let env = self.resolve(name.clone())?;
+    /// prevent assignments to the variables declared so far
+    pub fn freeze(&self) {
+        self.0.borrow_mut().read_only = true;
+    }
 
-        match env.variables.get(&name) {
+    pub fn get(&self, name: String) -> Result<LiteralValue> {
+        let scope = self.0.borrow();
+        match scope.variables.get(&name) {
             Some(val) => Ok(val.clone()),
-            None => return Err(AstError::NotDeclared(name).into()),
+            None => match &scope.parent {
+                Some(parent) => parent.get(name),
+                None => Err(AstError::NotDeclared(name).into()),
+            },
         }
     }
 
-    pub fn declare(&mut self, name: String, value: LiteralValue) -> Result<()> {
-        if self.variables.contains_key(&name) {
+    pub fn declare(&self, name: String, value: LiteralValue) -> Result<()> {
+        let mut scope = self.0.borrow_mut();
+        if scope.variables.contains_key(&name) {
             return Err(AstError::AlreadyDeclared(name).into());
         }
 
-        self.variables.insert(name.clone(), value);
+        scope.variables.insert(name, value);
 
         Ok(())
     }
 
-    pub fn assign(&mut self, name: String, value: LiteralValue) -> Result<()> {
-        let env = self.resolve(name.clone())?;
+    /// variables visible from this scope, built-ins are left out
+    pub fn variables(&self) -> BTreeMap<String, LiteralValue> {
+        let scope = self.0.borrow();
+        let mut variables = match &scope.parent {
+            Some(parent) => parent.variables(),
+            None => BTreeMap::new(),
+        };
+        if !scope.read_only {
+            // inner declarations shadow the outer ones
+            variables.extend(scope.variables.clone());
+        }
+        variables
+    }
+
+    /// names visible from this scope, built-ins included
+    pub fn names(&self) -> BTreeSet<String> {
+        let scope = self.0.borrow();
+        let mut names = match &scope.parent {
+            Some(parent) => parent.names(),
+            None => BTreeSet::new(),
+        };
+        names.extend(scope.variables.keys().cloned());
+        names
+    }
 
-        env.variables.entry(name).and_modify(|v| *v = value);
-        Ok(())
+    /// assign the variable in the innermost scope that declares it
+    pub fn assign(&self, name: String, value: LiteralValue) -> Result<()> {
+        let mut scope = self.0.borrow_mut();
+        let read_only = scope.read_only;
+        if let Some(variable) = scope.variables.get_mut(&name) {
+            if read_only {
+                return Err(AstError::ReadOnly(name).into());
+            }
+            *variable = value;
+            return Ok(());
+        }
+
+        match &scope.parent {
+            Some(parent) => parent.assign(name, value),
+            None => Err(AstError::NotDeclared(name).into()),
+        }
     }
 }
 
@@ -77,40 +131,86 @@ mod tests {
 
     #[test]
     fn declare() {
-        let mut env = Environment::new();
+        let env = Environment::new();
         env.declare("a".to_string(), LiteralValue::Boolean(false)).unwrap();
 
         let expected: BTreeMap<String, LiteralValue> = BTreeMap::from([("a".to_owned(), LiteralValue::Boolean(false))]);
 
-        assert_eq!(env.variables, expected);
+        assert_eq!(env.0.borrow().variables, expected);
     }
 
     #[test]
     fn assign() {
-        let mut env = Environment::new();
+        let env = Environment::new();
         env.declare("a".to_string(), LiteralValue::Boolean(false)).unwrap();
         env.assign("a".to_string(), LiteralValue::Boolean(true)).unwrap();
 
         let expected: BTreeMap<String, LiteralValue> = BTreeMap::from([("a".to_owned(), LiteralValue::Boolean(true))]);
 
-        assert_eq!(env.variables, expected);
+        assert_eq!(env.0.borrow().variables, expected);
     }
 
     #[test]
     fn declare_different_env() {
-        let mut parent_env = Environment::new();
+        let parent_env = Environment::new();
         parent_env.declare("a".to_string(), LiteralValue::Boolean(false)).unwrap();
-        
-        let mut child_env = Environment {
-            parent: Some(Rc::new(parent_env.clone())),
-            variables: BTreeMap::new(),
-        };
+
+        let child_env = parent_env.child();
         child_env.declare("a".to_string(), LiteralValue::Boolean(true)).unwrap();
 
         let expected_parent: BTreeMap<String, LiteralValue> = BTreeMap::from([("a".to_owned(), LiteralValue::Boolean(false))]);
         let expected_child: BTreeMap<String, LiteralValue> = BTreeMap::from([("a".to_owned(), LiteralValue::Boolean(true))]);
 
-        assert_eq!(parent_env.variables, expected_parent);
-        assert_eq!(child_env.variables, expected_child);
+        assert_eq!(parent_env.0.borrow().variables, expected_parent);
+        assert_eq!(child_env.0.borrow().variables, expected_child);
+    }
+
+    #[test]
+    fn assign_through_child_env() {
+        let parent_env = Environment::new();
+        parent_env.declare("a".to_string(), LiteralValue::Boolean(false)).unwrap();
+
+        let child_env = parent_env.child();
+        child_env.assign("a".to_string(), LiteralValue::Boolean(true)).unwrap();
+
+        assert_eq!(parent_env.get("a".to_string()).unwrap(), LiteralValue::Boolean(true));
+        assert!(child_env.0.borrow().variables.is_empty());
+    }
+
+    #[test]
+    fn visible_variables() {
+        let parent_env = Environment::new();
+        parent_env.declare("len".to_string(), LiteralValue::Int(0)).unwrap();
+        parent_env.freeze();
+
+        let env = parent_env.child();
+        env.declare("a".to_string(), LiteralValue::Boolean(false)).unwrap();
+        env.declare("b".to_string(), LiteralValue::Boolean(false)).unwrap();
+        let child_env = env.child();
+        child_env.declare("a".to_string(), LiteralValue::Boolean(true)).unwrap();
+
+        let expected: BTreeMap<String, LiteralValue> = BTreeMap::from([
+            ("a".to_owned(), LiteralValue::Boolean(true)),
+            ("b".to_owned(), LiteralValue::Boolean(false)),
+        ]);
+
+        assert_eq!(child_env.variables(), expected);
+        assert_eq!(child_env.names(), BTreeSet::from(["a".to_owned(), "b".to_owned(), "len".to_owned()]));
+    }
+
+    #[test]
+    fn frozen_env() {
+        let parent_env = Environment::new();
+        parent_env.declare("a".to_string(), LiteralValue::Boolean(false)).unwrap();
+        parent_env.freeze();
+
+        let child_env = parent_env.child();
+        let result = child_env.assign("a".to_string(), LiteralValue::Boolean(true));
+        assert_eq!(result.unwrap_err().to_string(), "Cannot assign to built-in 'a'");
+
+        // shadowing is still allowed
+        child_env.declare("a".to_string(), LiteralValue::Boolean(true)).unwrap();
+        child_env.assign("a".to_string(), LiteralValue::Null).unwrap();
+        assert_eq!(parent_env.get("a".to_string()).unwrap(), LiteralValue::Boolean(false));
     }
 }
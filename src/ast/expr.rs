@@ -1,8 +1,11 @@
 use anyhow::Result;
+use std::cmp::Ordering;
 use std::fmt::{self, Display, Formatter};
 
+use super::literal::LiteralValue;
+use super::runtime::{Flow, Runtime};
 use super::AstError;
-use super::{environment::Environment, literal::LiteralValue};
+use crate::diagnostic::{ContextKind, RuntimeError, Span};
 use crate::lexer::{Token, TokenType};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -44,7 +47,21 @@ pub enum Expr {
         callee: Box<Expr>,
         paren: Token,
         arguments: Vec<Expr>
-    }
+    },
+    List {
+        elements: Vec<Expr>,
+        bracket: Token,
+    },
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    /// interpolated string, its parts are joined as they are printed
+    Concat {
+        parts: Vec<Expr>,
+        token: Token,
+    },
 }
 
 impl Display for Expr {
@@ -59,8 +76,19 @@ impl Display for Expr {
             Expr::Variable { id } => write!(format, "(var {})", id.lexeme),
             Expr::Assign { id, value } => write!(format, "({} = {})", id.lexeme, value),
             Expr::Ignore { token } => write!(format, "(ignored Token {})", token),
-            Expr::Call { callee, paren: _, arguments } => write!(format, "(fn {} {:?})", callee, arguments),
-            
+            Expr::Call { callee, paren: _, arguments } => {
+                let arguments = arguments.iter().map(|argument| argument.to_string()).collect::<Vec<String>>();
+                write!(format, "(fn {} [{}])", callee, arguments.join(" "))
+            }
+            Expr::List { elements, bracket: _ } => {
+                let elements = elements.iter().map(|element| element.to_string()).collect::<Vec<String>>();
+                write!(format, "(list [{}])", elements.join(" "))
+            }
+            Expr::Index { object, bracket: _, index } => write!(format, "(index {} {})", object, index),
+            Expr::Concat { parts, token: _ } => {
+                let parts = parts.iter().map(|part| part.to_string()).collect::<Vec<String>>();
+                write!(format, "(concat [{}])", parts.join(" "))
+            }
         }
     }
 }
@@ -68,122 +96,182 @@ impl Display for Expr {
 impl Expr {
     fn evaluate_numeric_arithmetic_expression(&self, left: LiteralValue, right: LiteralValue, operator: &Token) -> Result<LiteralValue> {
         match operator.token_type {
-            TokenType::Plus => Ok(left + right),
-            TokenType::Minus => Ok(left - right),
-            TokenType::Star => Ok(left * right),
-            TokenType::Slash => {
-                if right == LiteralValue::Number(0.) {
-                    return Err(AstError::Division0(left, right).into());
-                }
-
-                Ok(left / right)
-            }
-            TokenType::Greater => Ok(self::LiteralValue::Boolean(left > right)),
-            TokenType::GreaterEqual => Ok(self::LiteralValue::Boolean(left >= right)),
-            TokenType::Less => Ok(self::LiteralValue::Boolean(left < right)),
-            TokenType::LessEqual => Ok(self::LiteralValue::Boolean(left <= right)),
-            TokenType::BangEqual => Ok(self::LiteralValue::Boolean(left != right)),
-            TokenType::EqualEqual => Ok(self::LiteralValue::Boolean(left == right)),
+            TokenType::Plus => left.checked_add(&right),
+            TokenType::Minus => left.checked_sub(&right),
+            TokenType::Star => left.checked_mul(&right),
+            TokenType::Slash => left.checked_div(&right),
+            TokenType::Greater => Ok(self::LiteralValue::Boolean(left.compare(&right)? == Some(Ordering::Greater))),
+            TokenType::GreaterEqual => Ok(self::LiteralValue::Boolean(matches!(left.compare(&right)?, Some(Ordering::Greater | Ordering::Equal)))),
+            TokenType::Less => Ok(self::LiteralValue::Boolean(left.compare(&right)? == Some(Ordering::Less))),
+            TokenType::LessEqual => Ok(self::LiteralValue::Boolean(matches!(left.compare(&right)?, Some(Ordering::Less | Ordering::Equal)))),
+            // values of different types are never equal
+            TokenType::BangEqual => Ok(self::LiteralValue::Boolean(!left.equals(&right))),
+            TokenType::EqualEqual => Ok(self::LiteralValue::Boolean(left.equals(&right))),
 
             _ => Err(AstError::InvalidOperator(operator.token_type).into()),
         }
     }
 
-    fn evaluate_string_expression(&self, left: LiteralValue, right: LiteralValue, operator: &Token) -> Result<LiteralValue> {
-        match operator.token_type {
-            TokenType::Greater => Ok(self::LiteralValue::Boolean(left > right)),
-            TokenType::GreaterEqual => Ok(self::LiteralValue::Boolean(left >= right)),
-            TokenType::Less => Ok(self::LiteralValue::Boolean(left < right)),
-            TokenType::LessEqual => Ok(self::LiteralValue::Boolean(left <= right)),
-            TokenType::BangEqual => Ok(self::LiteralValue::Boolean(left != right)),
-            TokenType::EqualEqual => Ok(self::LiteralValue::Boolean(left == right)),
-
-            _ => Err(AstError::InvalidOperator(operator.token_type).into()),
+    /// source covered by the tokens of the expression, literals don't keep their token
+    pub fn span(&self) -> Option<Span> {
+        let join = |spans: Vec<Option<Span>>| spans.into_iter().flatten().reduce(Span::join);
+        match self {
+            Expr::Binary { left, operator, right } | Expr::Logical { left, operator, right } => {
+                join(vec![left.span(), Some(operator.span()), right.span()])
+            }
+            Expr::Grouping { expr } => expr.span(),
+            Expr::Literal { .. } | Expr::ReservedLiteral { .. } => None,
+            Expr::Unary { operator, right } => join(vec![Some(operator.span()), right.span()]),
+            Expr::Variable { id } => Some(id.span()),
+            Expr::Assign { id, value } => join(vec![Some(id.span()), value.span()]),
+            Expr::Ignore { token } => Some(token.span()),
+            Expr::Call { callee, paren, .. } => join(vec![callee.span(), Some(paren.span())]),
+            Expr::List { elements, bracket } => {
+                let mut spans = elements.iter().map(|element| element.span()).collect::<Vec<Option<Span>>>();
+                spans.push(Some(bracket.span()));
+                join(spans)
+            }
+            Expr::Index { object, bracket, index } => join(vec![object.span(), Some(bracket.span()), index.span()]),
+            Expr::Concat { token, .. } => Some(token.span()),
         }
     }
 
-    fn evaluate_bool_expression(&self, left: LiteralValue, right: LiteralValue, operator: &Token) -> Result<LiteralValue> {
-        match operator.token_type {
-            TokenType::BangEqual => Ok(self::LiteralValue::Boolean(left != right)),
-            TokenType::EqualEqual => Ok(self::LiteralValue::Boolean(left == right)),
+    /// evaluate as an expression statement, discarding the value
+    ///
+    /// A function called as a statement can `wait`, its `return` ends at the call
+    pub fn execute(&self, runtime: &mut dyn Runtime) -> Result<Flow> {
+        let Expr::Call { callee, paren, arguments } = self else {
+            self.eval(runtime)?;
+            return Ok(Flow::Next);
+        };
 
-            _ => Err(AstError::InvalidOperator(operator.token_type).into()),
-        }
+        let call = |runtime: &mut dyn Runtime| -> Result<Flow> {
+            let callee = callee.eval(runtime)?;
+
+            let mut values: Vec<LiteralValue> = vec![];
+            for argument in arguments {
+                values.push(argument.eval(runtime)?);
+            }
+
+            match callee {
+                LiteralValue::Function(function) => match function.run(runtime, values, paren)? {
+                    Flow::Return(_) => Ok(Flow::Next),
+                    flow => Ok(flow),
+                },
+                LiteralValue::Native(function) => function.call(runtime, values, paren).map(|_| Flow::Next),
+                _ => Err(AstError::NotCallable(callee.to_string(), paren.line).into()),
+            }
+        };
+
+        call(runtime).map_err(|err| RuntimeError::context(err, ContextKind::Expression, || self.to_string(), self.span()))
+    }
+
+    pub fn eval(&self, runtime: &mut dyn Runtime) -> Result<LiteralValue> {
+        self.evaluate(runtime)
+            .map_err(|err| RuntimeError::context(err, ContextKind::Expression, || self.to_string(), self.span()))
     }
 
-    pub fn eval(&self, environment: &mut Environment) -> Result<LiteralValue> {
+    fn evaluate(&self, runtime: &mut dyn Runtime) -> Result<LiteralValue> {
         match self {
             Expr::Variable { id } => {
-                let val = environment.get(id.lexeme.to_owned())?;
+                let val = runtime.environment().get(id.lexeme.to_owned())?;
                 Ok(val)
             }
             Expr::Assign { id, value } => {
-                // environment.get(id.lexeme.to_owned())?;
-                let value = value.eval(&mut *environment)?;
-                environment.assign(id.lexeme.to_owned(), value)?;
-                Ok(environment.get(id.lexeme.to_owned())?)
+                // runtime.environment().get(id.lexeme.to_owned())?;
+                let value = value.eval(runtime)?;
+                runtime.environment().assign(id.lexeme.to_owned(), value)?;
+                Ok(runtime.environment().get(id.lexeme.to_owned())?)
             }
-            Expr::Grouping { expr } => expr.eval(environment),
+            Expr::Grouping { expr } => expr.eval(runtime),
             Expr::Literal { value } => Ok((*value).clone()),
             Expr::Logical { left, operator, right } => {
                 match operator.token_type {
                     TokenType::Or => {
-                        let left_val = left.eval(environment)?;
+                        let left_val = left.eval(runtime)?;
                         let bool_left = left_val.is_true()?;
 
                         if bool_left == LiteralValue::Boolean(true) {
                             Ok(left_val)
                         } else {
-                            right.eval(environment)
+                            right.eval(runtime)
                         }
                     },
                     TokenType::And => {
-                        let left_val = left.eval(environment)?;
+                        let left_val = left.eval(runtime)?;
                         let bool_left = left_val.is_true()?;
 
                         if bool_left == LiteralValue::Boolean(false) {
                             Ok(left_val)
                         } else {
-                            right.eval(environment)
+                            right.eval(runtime)
                         }
                     },
                     _ => Err(AstError::InvalidOperator(operator.token_type).into()),
                 }
             },
             Expr::Unary { operator, right } => {
-                let rhs = right.eval(environment)?;
+                let rhs = right.eval(runtime)?;
 
-                let result = match (&rhs, operator.token_type) {
-                    (LiteralValue::Number(num), TokenType::Minus) => Ok(LiteralValue::Number(-num)),
-                    (_, TokenType::Minus) => Err(AstError::Unimplemented(TokenType::Minus, rhs).into()),
+                match (&rhs, operator.token_type) {
+                    (LiteralValue::Int(num), TokenType::Minus) => match num.checked_neg() {
+                        Some(num) => Ok(LiteralValue::Int(num)),
+                        None => Err(AstError::UnaryOverflow(operator.lexeme.clone(), rhs.to_string()).into()),
+                    },
+                    (LiteralValue::Float(num), TokenType::Minus) => Ok(LiteralValue::Float(-num)),
+                    (_, TokenType::Minus) => Err(AstError::Unimplemented(TokenType::Minus, rhs.to_string()).into()),
                     (any, TokenType::Bang) => Ok(any.is_false()?),
                     _ => Err(AstError::Unreachable(self.to_string()).into()),
-                };
-
-                result
+                }
             }
             Expr::Binary { left, operator, right } => {
-                let lhs = left.eval(environment)?;
-                let rhs = right.eval(environment)?;
-
-                if matches!(lhs, LiteralValue::Number(_)) && matches!(rhs, LiteralValue::Number(_)) {
-                    return self.evaluate_numeric_arithmetic_expression(lhs, rhs, operator);
-                } else if matches!(lhs, LiteralValue::StringValue(_)) && matches!(rhs, LiteralValue::StringValue(_)) {
-                    return self.evaluate_string_expression(lhs, rhs, operator);
-                } else if matches!(lhs, LiteralValue::Boolean(_)) && matches!(rhs, LiteralValue::Boolean(_)) {
-                    return self.evaluate_bool_expression(lhs, rhs, operator);
+                let lhs = left.eval(runtime)?;
+                let rhs = right.eval(runtime)?;
+
+                if matches!(operator.token_type, TokenType::Range | TokenType::RangeEqual) {
+                    LiteralValue::range(&lhs, &rhs, operator)
                 } else {
-                    return Err(AstError::InvalidOperation(lhs, operator.lexeme.clone(), rhs).into());
+                    self.evaluate_numeric_arithmetic_expression(lhs, rhs, operator)
                 }
-            } 
+            }
             Expr::Ignore { token: _ } => Ok(LiteralValue::Null),
-            Expr::ReservedLiteral { value } => {
-                println!("TODO implement IT inside for loop. {}", value);
-                todo!()
+            Expr::ReservedLiteral { value } => match runtime.iteration() {
+                Some(iteration) => Ok(LiteralValue::Int(iteration as i64)),
+                None => Err(AstError::NoIteration(value.clone()).into()),
             },
             Expr::Call { callee, paren, arguments } => {
-                todo!()
-            },
+                let callee = callee.eval(runtime)?;
+
+                let mut values: Vec<LiteralValue> = vec![];
+                for argument in arguments {
+                    values.push(argument.eval(runtime)?);
+                }
+
+                match callee {
+                    LiteralValue::Function(function) => function.call(runtime, values, paren),
+                    LiteralValue::Native(function) => function.call(runtime, values, paren),
+                    _ => Err(AstError::NotCallable(callee.to_string(), paren.line).into()),
+                }
+            }
+            Expr::List { elements, bracket: _ } => {
+                let mut values: Vec<LiteralValue> = vec![];
+                for element in elements {
+                    values.push(element.eval(runtime)?);
+                }
+                Ok(LiteralValue::List(values))
+            }
+            Expr::Index { object, bracket: _, index } => {
+                let object = object.eval(runtime)?;
+                let index = index.eval(runtime)?;
+                object.index(&index)
+            }
+            Expr::Concat { parts, token: _ } => {
+                let mut concatenated = "".to_owned();
+                for part in parts {
+                    concatenated += &part.eval(runtime)?.to_string();
+                }
+                Ok(LiteralValue::StringValue(concatenated))
+            }
         }
     }
 }
@@ -198,17 +286,17 @@ mod tests {
     fn pretty_print_ast() {
         use Expr::*;
 
-        let minus_token = Token::new(TokenType::Minus, "-".to_string(), None, 0, 0);
+        let minus_token = Token::new(TokenType::Minus, "-".to_string(), None, 0, 0, 0);
         let num = Literal {
-            value: LiteralValue::Number(123.),
+            value: LiteralValue::Int(123),
         };
 
         let group = Grouping {
             expr: Box::new(Literal {
-                value: LiteralValue::Number(45.),
+                value: LiteralValue::Int(45),
             }),
         };
-        let multi = Token::new(TokenType::Star, "*".to_string(), None, 0, 0);
+        let multi = Token::new(TokenType::Star, "*".to_string(), None, 0, 0, 0);
 
         let ast = Binary {
             left: Box::new(Unary {
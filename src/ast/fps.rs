@@ -1,113 +1,283 @@
 use anyhow::Result;
-use std::{collections::BTreeMap, ops::Range};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Display, Formatter};
 
-use super::{environment::Environment, statement::Statement};
-use crate::ast::LiteralValue as AstLiteralValue;
+use super::{environment::Environment, expr::Expr, statement::Statement, AstError};
+use crate::ast::literal::LiteralValue as AstLiteralValue;
 use crate::lexer::{LiteralValue, Token};
 
+/// Statements scheduled to run once on a frame
+#[derive(Debug, Clone)]
+pub struct FrameBlock {
+    pub statements: Vec<Statement>,
+    /// indexes of the `for` loop iterations the statements belong to, innermost last
+    pub iterations: Vec<usize>,
+    /// number of consecutive frames the statements are repeated on, a `for` loop spreads its iterations by it
+    pub span: usize,
+    /// scope the statements were scheduled from, `None` runs them in the current environment
+    pub environment: Option<Environment>,
+}
+
+/// Work waiting in the frame queue
+#[derive(Debug, Clone)]
+pub enum Task {
+    /// top-level statements of a program, resumed on the frame its next section starts
+    Program {
+        statements: Vec<Statement>,
+        /// index of the frame marker the section starts at, its duration is evaluated once the section starts
+        position: usize,
+    },
+    Section(Section),
+    /// blocks suspended by `wait`, innermost first
+    Resume(Vec<Suspended>),
+    Timer(Timer),
+    Iteration(Iteration),
+}
+
+impl Task {
+    /// `every` blocks repeat until the program ends, they don't keep it running on their own
+    pub fn is_daemon(&self) -> bool {
+        matches!(self, Task::Timer(Timer { interval: Some(_), .. }))
+    }
+}
+
+// one line summaries, nested blocks are elided like in statements
+impl Display for Task {
+    fn fmt(&self, format: &mut Formatter) -> fmt::Result {
+        let statements = |statements: &[Statement]| statements.iter().map(|statement| statement.to_string()).collect::<Vec<String>>().join(" ");
+
+        match self {
+            Task::Program { statements, position } => match statements.get(*position) {
+                Some(marker @ (Statement::Fps { .. } | Statement::FpsForever(_))) => write!(format, "section {}", marker),
+                _ => write!(format, "program start"),
+            },
+            Task::Section(section) => write!(format, "repeat: {}", statements(section.body())),
+            Task::Resume(suspended) => match suspended.first().map(|block| &block.kind) {
+                Some(Resume::Block(rest)) => write!(format, "resume: {}", statements(rest)),
+                Some(Resume::While { condition, .. }) => write!(format, "resume: while {} {{ .. }}", condition),
+                Some(Resume::Call) | None => write!(format, "resume: return from call"),
+            },
+            Task::Timer(Timer { id, interval, block }) => match interval {
+                Some(interval) => write!(format, "every {} <timer {}>: {}", interval, id, statements(&block.statements)),
+                None => write!(format, "at <timer {}>: {}", id, statements(&block.statements)),
+            },
+            Task::Iteration(iteration) => write!(format, "iteration {}: {}", iteration.index, statements(&iteration.block.statements)),
+        }
+    }
+}
+
+/// Frame of a program section after its first one, it schedules the next one when it runs like a loop iteration
+#[derive(Debug, Clone)]
+pub struct Section {
+    /// top-level statements of the program, the next section starts after this one
+    pub statements: Vec<Statement>,
+    /// index of the frame marker the section starts at
+    pub position: usize,
+    /// frames of the section already run, the last one is `duration - 1`
+    pub index: usize,
+    pub duration: usize,
+}
+
+impl Section {
+    /// statements repeated on every frame of the section
+    pub fn body(&self) -> &[Statement] {
+        let (start, end) = section_bounds(&self.statements, self.position);
+        &self.statements[start..end]
+    }
+}
+
+/// start and end of the section of a program at `position`, from its frame marker to the next one
+///
+/// Statements before the first marker make a section of their own
+pub fn section_bounds(statements: &[Statement], position: usize) -> (usize, usize) {
+    let start = match statements.get(position) {
+        Some(Statement::Fps { .. } | Statement::FpsForever(_)) => position + 1,
+        _ => position,
+    };
+    let end = statements[start..]
+        .iter()
+        .position(|statement| matches!(statement, Statement::Fps { .. } | Statement::FpsForever(_) | Statement::FpsEnd(_)))
+        .map_or(statements.len(), |offset| start + offset);
+    (start, end)
+}
+
+/// `every` or `at` block, its pending runs are dropped when its handle is cancelled
+#[derive(Debug, Clone)]
+pub struct Timer {
+    pub id: usize,
+    /// frames between the runs of an `every` block, an `at` block runs once
+    pub interval: Option<usize>,
+    /// every run gets its own scope, nested in the block environment
+    pub block: FrameBlock,
+}
+
+/// Iteration of a `for` loop, it schedules the next one when it runs so the loop advances one frame at a time
+#[derive(Debug, Clone)]
+pub struct Iteration {
+    pub index: usize,
+    /// iterations of the loop, the last one is `end - 1`
+    pub end: usize,
+    /// frames between two iterations
+    pub step: usize,
+    /// every iteration gets its own scope, nested in the block environment, and is appended to the block iterations
+    pub block: FrameBlock,
+}
+
+/// What is left to run of a block suspended by `wait`
+#[derive(Debug, Clone)]
+pub enum Resume {
+    /// remaining statements of a block
+    Block(Vec<Statement>),
+    /// `while` loop, the condition is checked again once the body resumed
+    While { condition: Expr, body: Vec<Statement> },
+    /// end of a function called as a statement, a `return` resumes after it
+    Call,
+}
+
+/// Suspended block with the state it was running in
+#[derive(Debug, Clone)]
+pub struct Suspended {
+    pub kind: Resume,
+    pub environment: Environment,
+    pub iterations: Vec<usize>,
+    pub span: usize,
+}
+
+/// Task in the frame queue with the program it belongs to, a REPL session runs a program per input
+#[derive(Debug, Clone)]
+pub struct Scheduled {
+    pub program: usize,
+    pub task: Task,
+}
+
+/// Frame scheduler, tasks are enqueued for future frames while statements execute
 #[derive(Debug)]
 pub struct Fps {
-    pub frames: BTreeMap<usize, Vec<Statement>>,
-    current_range: Range<usize>,
+    pub frames: BTreeMap<usize, VecDeque<Scheduled>>,
+    // last timer id handed out
+    timers: usize,
+    // last program id handed out
+    programs: usize,
+    // program of the running task, the tasks it schedules belong to it too
+    program: usize,
 }
 
 impl Fps {
     pub fn new() -> Self {
         Self {
             frames: BTreeMap::new(),
-            current_range: 0..1,
+            timers: 0,
+            programs: 0,
+            program: 0,
         }
     }
 
-    fn get_fps_duration_from_token(&self, token: &Token) -> usize {
+    /// duration of a `#N` frame marker
+    pub fn get_fps_duration_from_token(token: &Token) -> Result<usize> {
         match &token.literal {
-            Some(fps) => match fps {
-                &LiteralValue::Fps(x) => x,
-                _ => panic!("Unexpected literal for FPS: {}", fps),
-            },
-            None => 0,
+            Some(LiteralValue::Fps(x)) if *x >= 1 => Ok(*x),
+            _ => Err(AstError::FpsDuration(token.lexeme.clone()).into()),
         }
     }
 
-    fn add_buf_statements_to_frame(&mut self, buf: &Vec<Statement>) {
-        // println!("current range {:?}", self.current_range);
-        // println!("current buf {:?}", buf);
-        let mut buf_statements: Vec<Statement> = vec![];
-        for fps in self.current_range.clone() {
-            // println!("fps {}  (added + 1)", fps + 1);
-            for statement in buf {
-                buf_statements.push(statement.clone());
-            }
-            self.frames
-                .entry(fps + 1)
-                .and_modify(|x| x.extend(buf_statements.clone()))
-                .or_insert(buf_statements.clone());
-            buf_statements.clear();
+    /// duration of a `#(expr)` frame marker, frame counts are ints
+    pub fn get_fps_duration_from_value(value: &AstLiteralValue) -> Result<usize> {
+        match value {
+            AstLiteralValue::Int(x) if *x >= 1 => Ok(*x as usize),
+            _ => Err(AstError::FpsDuration(value.to_string()).into()),
         }
     }
 
-    fn get_fps_duration_from_statement(&self, environment: &mut Environment, fps_statement: &Statement) -> Result<usize> {
-        match fps_statement {
-            Statement::Fps(next_fps) => Ok(self.get_fps_duration_from_token(&next_fps)),
-            Statement::FpsEnd(_) => Ok(1),
-            Statement::For { expr: range, for_block: _ } => {
-                let range = range.eval(environment)?;
-                match range {
-                    AstLiteralValue::Range((start, end)) => Ok(end - start - 1),
-                    AstLiteralValue::RangeEqual((start, end)) => Ok(end - start),
-                    _ => panic!(),
-                }
-            }
-            _ => panic!("cannot retrieve fps duration from this statement {:?}", fps_statement),
+    /// frame of an `at N` block, frames that already ran can't be scheduled
+    pub fn get_frame_from_value(value: &AstLiteralValue, current: usize) -> Result<usize> {
+        match value {
+            AstLiteralValue::Int(x) if *x >= 0 && *x as usize >= current => Ok(*x as usize),
+            _ => Err(AstError::Frame(value.to_string(), current).into()),
+        }
+    }
+
+    /// number of iterations of a `for` loop over the given range
+    pub fn get_iterations_from_range(range: &AstLiteralValue) -> Result<usize> {
+        match range {
+            // empty when the end is below the start
+            AstLiteralValue::Range((start, end)) => Ok(usize::try_from(end.saturating_sub(*start)).unwrap_or(0)),
+            AstLiteralValue::RangeEqual((start, end)) => Ok(usize::try_from(end.saturating_sub(*start).saturating_add(1)).unwrap_or(0)),
+            _ => Err(AstError::ExpectedRange(range.to_string()).into()),
         }
     }
 
-    pub fn allocate_statements_to_frame(&mut self, environment: &mut Environment, statements: Vec<Statement>) -> Result<()> {
-        let mut buf_fps_statements: Vec<Statement> = vec![];
+    /// enqueue a task of the running program on a frame, after the tasks already scheduled on it
+    pub fn schedule(&mut self, frame: usize, task: Task) {
+        let program = self.program;
+        self.frames.entry(frame).or_default().push_back(Scheduled { program, task });
+    }
 
-        for statement in statements {
-            match statement {
-                Statement::Fps(_) => {
-                    self.add_buf_statements_to_frame(&buf_fps_statements);
-                    let next_fps: usize = self.get_fps_duration_from_statement(environment, &statement)?;
-                    self.current_range = self.current_range.end..self.current_range.end + next_fps;
-                    buf_fps_statements.clear();
-                }
-                Statement::FpsEnd(_) => {
-                    self.add_buf_statements_to_frame(&buf_fps_statements);
-                    self.current_range = self.current_range.end..self.current_range.end;
-                    buf_fps_statements.clear();
-                }
-                Statement::Comment(_) => {
-                    // ignore
-                }
-                Statement::For { expr: _, ref for_block } => {
-                    // clear buf first (if statements before the for block?)
-                    self.add_buf_statements_to_frame(&buf_fps_statements);
-                    buf_fps_statements.clear();
-                    // close test
-
-                    let current_range = self.current_range.clone();
-
-                    let fps_duration = self.get_fps_duration_from_statement(environment, &statement)?;
-                    buf_fps_statements.extend(for_block.clone());
-                    if self.current_range.end == 1 { // At frame 0 we can't subtract by 1 at the end
-                        self.current_range = self.current_range.start..self.current_range.end + (self.current_range.end * fps_duration);
-                    } else {
-                        self.current_range = self.current_range.start..self.current_range.end + (self.current_range.end * fps_duration) - 1;
-                    }
-                    // println!("current_range for {:?}", self.current_range);
-                    self.add_buf_statements_to_frame(&buf_fps_statements);
-                    buf_fps_statements.clear();
-
-                    self.current_range = current_range;
+    /// start a new program, the tasks scheduled from now on belong to it
+    pub fn start_program(&mut self) {
+        self.programs += 1;
+        self.program = self.programs;
+    }
+
+    /// program the running task belongs to
+    pub fn program(&self) -> usize {
+        self.program
+    }
+
+    /// program started last
+    pub fn last_program(&self) -> usize {
+        self.programs
+    }
+
+    /// drop the tasks left of the running program, the ones of other programs keep running
+    pub fn drop_program(&mut self) {
+        let program = self.program;
+        for tasks in self.frames.values_mut() {
+            tasks.retain(|scheduled| scheduled.program != program);
+        }
+        self.frames.retain(|_, tasks| !tasks.is_empty());
+    }
+
+    /// id of a new `every` or `at` block
+    pub fn timer_id(&mut self) -> usize {
+        self.timers += 1;
+        self.timers
+    }
+
+    /// drop the pending runs of a timer, false when it had none left
+    pub fn cancel(&mut self, id: usize) -> bool {
+        let mut cancelled = false;
+        for tasks in self.frames.values_mut() {
+            tasks.retain(|scheduled| match &scheduled.task {
+                Task::Timer(timer) if timer.id == id => {
+                    cancelled = true;
+                    false
                 }
-                _ => buf_fps_statements.push(statement.clone()),
-            }
+                _ => true,
+            });
         }
+        self.frames.retain(|_, tasks| !tasks.is_empty());
+        cancelled
+    }
 
-        // println!("> frames: {:?}", self.frames);
+    /// only `every` blocks are left, the program is over
+    pub fn is_idle(&self) -> bool {
+        self.frames.values().flatten().all(|scheduled| scheduled.task.is_daemon())
+    }
 
-        Ok(())
+    /// first frame with scheduled tasks
+    pub fn next_frame(&self) -> Option<usize> {
+        self.frames.keys().next().copied()
+    }
+
+    /// take the next task of a frame and make its program the running one, tasks can still be enqueued on the frame while it runs
+    pub fn next_task(&mut self, frame: usize) -> Option<Task> {
+        let tasks = self.frames.get_mut(&frame)?;
+        let scheduled = tasks.pop_front();
+        if tasks.is_empty() {
+            self.frames.remove(&frame);
+        }
+        let scheduled = scheduled?;
+        self.program = scheduled.program;
+        Some(scheduled.task)
     }
 }
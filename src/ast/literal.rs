@@ -1,25 +1,37 @@
 use anyhow::Result;
+use std::cmp::Ordering;
 use std::fmt::{self, Display, Formatter};
-use std::ops::{Add, Div, Mul, Sub};
+use std::rc::Rc;
 
-use super::AstError;
+use super::{
+    callable::{Function, NativeFunction},
+    AstError,
+};
 use crate::lexer::{self, Token, TokenType};
 
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum LiteralValue {
-    Number(f64),
+    Int(i64),
+    Float(f64),
     StringValue(String),
     Boolean(bool),
-    Range((usize, usize)),
-    RangeEqual((usize, usize)),
+    Range((i64, i64)),
+    RangeEqual((i64, i64)),
+    List(Vec<LiteralValue>),
+    Function(Rc<Function>),
+    Native(Rc<NativeFunction>),
+    /// handle of an `every` or `at` block
+    Timer(usize),
     Null,
 }
 
 impl Display for LiteralValue {
     fn fmt(&self, format: &mut Formatter) -> fmt::Result {
         match self {
-            LiteralValue::Number(val) => write!(format, "{}", val.to_string()),
+            LiteralValue::Int(val) => write!(format, "{}", val),
+            // keep the decimal point so floats can be told apart from ints
+            LiteralValue::Float(val) => write!(format, "{:?}", val),
             LiteralValue::StringValue(val) => write!(format, "{}", val),
             LiteralValue::Boolean(val) => match val {
                 true => write!(format, "true"),
@@ -28,89 +40,174 @@ impl Display for LiteralValue {
             LiteralValue::Null => write!(format, "Null"),
             LiteralValue::Range(val) => write!(format, "{:?}", val),
             LiteralValue::RangeEqual(val) => write!(format, "{:?}", val),
+            LiteralValue::List(values) => {
+                // quote strings so `["a, b"]` can be told apart from `["a", "b"]`
+                let values = values
+                    .iter()
+                    .map(|value| match value {
+                        LiteralValue::StringValue(val) => format!("{:?}", val),
+                        _ => value.to_string(),
+                    })
+                    .collect::<Vec<String>>();
+                write!(format, "[{}]", values.join(", "))
+            }
+            LiteralValue::Function(function) => write!(format, "{:?}", function),
+            LiteralValue::Native(function) => write!(format, "{:?}", function),
+            LiteralValue::Timer(id) => write!(format, "<timer {}>", id),
         }
     }
 }
 
-impl Into<f64> for LiteralValue {
-    fn into(self) -> f64 {
-        match self {
-            LiteralValue::Number(x) => x,
-            LiteralValue::Boolean(_) => panic!("Bool cannot be cast into f64"),
-            _ => todo!(),
+impl LiteralValue {
+    pub fn from_token(token: Token) -> Result<Self> {
+        use TokenType::*;
+        match token.token_type {
+            StringLiteral => Ok(Self::StringValue(unwrap_as_string(token.literal)?)),
+            Number => match token.literal {
+                Some(lexer::LiteralValue::Int(_)) => Ok(Self::Int(unwrap_as_i64(token.literal)?)),
+                _ => Ok(Self::Float(unwrap_as_f64(token.literal)?)),
+            },
+            True => Ok(Self::Boolean(true)),
+            False => Ok(Self::Boolean(false)),
+            Null => Ok(Self::Null),
+            _ => Err(AstError::LiteralValueCreate(token).into()),
         }
     }
-}
 
-impl Add for LiteralValue {
-    type Output = Self;
-
-    fn add(self, other: Self) -> Self {
+    /// name of the type of the value, as written in the docs
+    pub fn type_name(&self) -> &'static str {
         match self {
-            LiteralValue::Number(x) => LiteralValue::Number(x + <LiteralValue as Into<f64>>::into(other)),
-            LiteralValue::Boolean(val) => panic!("Cannot Add bool '{val}' with number"),
-            _ => todo!(),
+            LiteralValue::Int(_) => "Int",
+            LiteralValue::Float(_) => "Float",
+            LiteralValue::StringValue(_) => "String",
+            LiteralValue::Boolean(_) => "Boolean",
+            LiteralValue::Range(_) => "Range",
+            LiteralValue::RangeEqual(_) => "RangeEqual",
+            LiteralValue::List(_) => "List",
+            LiteralValue::Function(_) | LiteralValue::Native(_) => "Function",
+            LiteralValue::Timer(_) => "Timer",
+            LiteralValue::Null => "Null",
         }
     }
-}
-impl Sub for LiteralValue {
-    type Output = Self;
 
-    fn sub(self, other: Self) -> Self {
+    /// type and value of an operand in error messages, strings are quoted to tell them apart from other values
+    fn operand(&self) -> String {
         match self {
-            LiteralValue::Number(x) => LiteralValue::Number(x - <LiteralValue as Into<f64>>::into(other)),
-            LiteralValue::Boolean(val) => panic!("Cannot Subtract bool '{val}' with number"),
-            _ => todo!(),
+            LiteralValue::StringValue(val) => format!("{} {:?}", self.type_name(), val),
+            _ => format!("{} {}", self.type_name(), self),
         }
     }
-}
-impl Mul for LiteralValue {
-    type Output = Self;
 
-    fn mul(self, other: Self) -> Self {
+    pub fn is_number(&self) -> bool {
+        matches!(self, LiteralValue::Int(_) | LiteralValue::Float(_))
+    }
+
+    fn as_f64(&self) -> Option<f64> {
         match self {
-            LiteralValue::Number(x) => LiteralValue::Number(x * <LiteralValue as Into<f64>>::into(other)),
-            LiteralValue::Boolean(val) => panic!("Cannot Multiply bool '{val}' with number"),
-            _ => todo!(),
+            LiteralValue::Int(x) => Some(*x as f64),
+            LiteralValue::Float(x) => Some(*x),
+            _ => None,
         }
     }
-}
-impl Div for LiteralValue {
-    type Output = Self;
 
-    fn div(self, other: Self) -> Self {
-        match self {
-            LiteralValue::Number(x) => LiteralValue::Number(x / <LiteralValue as Into<f64>>::into(other)),
-            LiteralValue::Boolean(val) => panic!("Cannot Divide bool '{val}' with number"),
-            _ => todo!(),
+    /// `+` adds numbers and concatenates strings, a number added to a string is concatenated as it is printed
+    pub fn checked_add(&self, other: &LiteralValue) -> Result<Self> {
+        match (self, other) {
+            (LiteralValue::StringValue(x), LiteralValue::StringValue(y)) => Ok(LiteralValue::StringValue(format!("{}{}", x, y))),
+            (LiteralValue::StringValue(_), LiteralValue::Int(_) | LiteralValue::Float(_))
+            | (LiteralValue::Int(_) | LiteralValue::Float(_), LiteralValue::StringValue(_)) => {
+                Ok(LiteralValue::StringValue(format!("{}{}", self, other)))
+            }
+            _ => self.arithmetic("+", other, i64::checked_add, |x, y| x + y),
         }
     }
-}
 
-impl LiteralValue {
-    pub fn from_token(token: Token) -> Result<Self> {
-        use TokenType::*;
-        match token.token_type {
-            StringLiteral => Ok(Self::StringValue(unwrap_as_string(token.literal)?)),
-            Number => Ok(Self::Number(unwrap_as_f64(token.literal)?)),
-            True => Ok(Self::Boolean(true)),
-            False => Ok(Self::Boolean(false)),
-            Range => {
-                let split = token.lexeme.split("..").collect::<Vec<&str>>();
-                Ok(Self::Range((split.get(0).unwrap().parse::<usize>().unwrap(), split.get(1).unwrap().parse::<usize>().unwrap())))
+    pub fn checked_sub(&self, other: &LiteralValue) -> Result<Self> {
+        self.arithmetic("-", other, i64::checked_sub, |x, y| x - y)
+    }
+
+    pub fn checked_mul(&self, other: &LiteralValue) -> Result<Self> {
+        self.arithmetic("*", other, i64::checked_mul, |x, y| x * y)
+    }
+
+    pub fn checked_div(&self, other: &LiteralValue) -> Result<Self> {
+        if self.is_number() && other.as_f64() == Some(0.) {
+            return Err(AstError::Division0(self.to_string(), other.to_string()).into());
+        }
+        self.arithmetic("/", other, i64::checked_div, |x, y| x / y)
+    }
+
+    /// arithmetic between any two values, only numbers support it
+    ///
+    /// Ints stay ints, with integer division and overflow errors, and are promoted to floats when mixed with one
+    fn arithmetic(&self, symbol: &str, other: &LiteralValue, int: fn(i64, i64) -> Option<i64>, float: fn(f64, f64) -> f64) -> Result<Self> {
+        match (self, other) {
+            (LiteralValue::Int(x), LiteralValue::Int(y)) => match int(*x, *y) {
+                Some(result) => Ok(LiteralValue::Int(result)),
+                None => Err(AstError::Overflow(self.to_string(), symbol.to_owned(), other.to_string()).into()),
+            },
+            _ => match (self.as_f64(), other.as_f64()) {
+                (Some(x), Some(y)) => Ok(LiteralValue::Float(float(x, y))),
+                _ => Err(AstError::InvalidOperation(self.operand(), symbol.to_owned(), other.operand()).into()),
+            },
+        }
+    }
+
+    /// `==` between any two values, numbers are equal by value whatever their type
+    pub fn equals(&self, other: &LiteralValue) -> bool {
+        match (self.as_f64(), other.as_f64()) {
+            (Some(_), Some(_)) => self.compare(other).ok().flatten() == Some(Ordering::Equal),
+            _ => self == other,
+        }
+    }
+
+    /// ordering of two numbers or two strings, ints are compared to floats by value
+    pub fn compare(&self, other: &LiteralValue) -> Result<Option<Ordering>> {
+        match (self, other) {
+            (LiteralValue::Int(x), LiteralValue::Int(y)) => Ok(Some(x.cmp(y))),
+            (LiteralValue::StringValue(x), LiteralValue::StringValue(y)) => Ok(Some(x.cmp(y))),
+            _ => match (self.as_f64(), other.as_f64()) {
+                (Some(x), Some(y)) => Ok(x.partial_cmp(&y)),
+                _ => Err(AstError::Incomparable(self.operand(), other.operand()).into()),
             },
-            RangeEqual => {
-                let split = token.lexeme.split("..=").collect::<Vec<&str>>();
-                Ok(Self::RangeEqual((split.get(0).unwrap().parse::<usize>().unwrap(), split.get(1).unwrap().parse::<usize>().unwrap())))
+        }
+    }
+
+    /// value of `self[index]`, an int picks a single char or element and a range slices
+    ///
+    /// Strings are indexed by chars, not bytes
+    pub fn index(&self, index: &LiteralValue) -> Result<Self> {
+        match self {
+            LiteralValue::StringValue(val) => {
+                let chars = val.chars().collect::<Vec<char>>();
+                match index {
+                    LiteralValue::Int(_) => Ok(Self::StringValue(chars[index_in(index, chars.len())?].to_string())),
+                    _ => Ok(Self::StringValue(chars[slice_in(index, chars.len())?].iter().collect())),
+                }
+            }
+            LiteralValue::List(values) => match index {
+                LiteralValue::Int(_) => Ok(values[index_in(index, values.len())?].clone()),
+                _ => Ok(Self::List(values[slice_in(index, values.len())?].to_vec())),
             },
-            _ => return Err(AstError::LiteralValueCreate(token).into()),
+            _ => Err(AstError::NotIndexable(self.to_string()).into()),
+        }
+    }
+
+    /// build the value of a `..`/`..=` expression from its evaluated bounds
+    pub fn range(start: &LiteralValue, end: &LiteralValue, operator: &Token) -> Result<Self> {
+        let bounds = (unwrap_as_bound(start)?, unwrap_as_bound(end)?);
+        match operator.token_type {
+            TokenType::Range => Ok(Self::Range(bounds)),
+            TokenType::RangeEqual => Ok(Self::RangeEqual(bounds)),
+            _ => Err(AstError::InvalidOperator(operator.token_type).into()),
         }
     }
 
     pub fn is_false(&self) -> Result<LiteralValue> {
         use LiteralValue::*;
         match self {
-            Number(num) => {
+            Int(num) => Ok(LiteralValue::Boolean(*num == 0)),
+            Float(num) => {
                 if *num == 0. {
                     Ok(LiteralValue::Boolean(true))
                 } else {
@@ -118,7 +215,7 @@ impl LiteralValue {
                 }
             }
             StringValue(val) => {
-                if val.len() == 0 {
+                if val.is_empty() {
                     Ok(LiteralValue::Boolean(true))
                 } else {
                     Ok(LiteralValue::Boolean(false))
@@ -126,6 +223,8 @@ impl LiteralValue {
             }
             Boolean(val) => Ok(LiteralValue::Boolean(!*val)),
             Null => Ok(LiteralValue::Boolean(true)),
+            List(values) => Ok(LiteralValue::Boolean(values.is_empty())),
+            Function(_) | Native(_) | Timer(_) => Ok(LiteralValue::Boolean(false)),
             Range(_) | RangeEqual(_) => Err(AstError::CompareTypes("Range or RangeEqual".to_owned()).into()),
         }
     }
@@ -133,7 +232,8 @@ impl LiteralValue {
     pub fn is_true(&self) -> Result<LiteralValue> {
         use LiteralValue::*;
         match self {
-            Number(num) => {
+            Int(num) => Ok(LiteralValue::Boolean(*num != 0)),
+            Float(num) => {
                 if *num == 0. {
                     Ok(LiteralValue::Boolean(false))
                 } else {
@@ -141,7 +241,7 @@ impl LiteralValue {
                 }
             }
             StringValue(val) => {
-                if val.len() == 0 {
+                if val.is_empty() {
                     Ok(LiteralValue::Boolean(false))
                 } else {
                     Ok(LiteralValue::Boolean(true))
@@ -149,6 +249,8 @@ impl LiteralValue {
             }
             Boolean(val) => Ok(LiteralValue::Boolean(*val)),
             Null => Ok(LiteralValue::Boolean(false)),
+            List(values) => Ok(LiteralValue::Boolean(!values.is_empty())),
+            Function(_) | Native(_) | Timer(_) => Ok(LiteralValue::Boolean(true)),
             Range(_) | RangeEqual(_) => Err(AstError::CompareTypes("Range or RangeEqual".to_owned()).into()),
         }
     }
@@ -158,7 +260,7 @@ fn unwrap_as_string(literal: Option<lexer::LiteralValue>) -> Result<String> {
     match literal {
         Some(lexer::LiteralValue::StringValue(s)) => Ok(s.clone()),
         Some(lexer::LiteralValue::Identifier(s)) => Ok(s.clone()),
-        _ => return Err(AstError::UnwrapString(literal).into()),
+        _ => Err(AstError::UnwrapString(literal).into()),
     }
 }
 
@@ -166,13 +268,121 @@ fn unwrap_as_f64(literal: Option<lexer::LiteralValue>) -> Result<f64> {
     // println!("{:?}", literal);
     match literal {
         Some(lexer::LiteralValue::Float(s)) => Ok(s),
-        _ => return Err(AstError::UnwrapFloat(literal).into()),
+        _ => Err(AstError::UnwrapFloat(literal).into()),
     }
 }
 
-// fn unwrap_as_usize(literal: Option<lexer::LiteralValue>) -> Result<usize> {
-//     match literal {
-//         Some(lexer::LiteralValue::Float(s)) => Ok(s),
-//         _ => return Err(AstError::UnwrapInt(literal).into()),
-//     }
-// }
+fn unwrap_as_bound(value: &LiteralValue) -> Result<i64> {
+    match value {
+        LiteralValue::Int(x) => Ok(*x),
+        _ => Err(AstError::RangeBound(value.to_string()).into()),
+    }
+}
+
+/// position of an int index into a sequence of `len` items
+fn index_in(index: &LiteralValue, len: usize) -> Result<usize> {
+    match index {
+        LiteralValue::Int(i) if *i >= 0 && (*i as u64) < len as u64 => Ok(*i as usize),
+        LiteralValue::Int(_) => Err(AstError::IndexOutOfBounds(index.to_string(), len).into()),
+        _ => Err(AstError::InvalidIndex(index.to_string()).into()),
+    }
+}
+
+/// positions covered by a range index into a sequence of `len` items
+fn slice_in(index: &LiteralValue, len: usize) -> Result<std::ops::Range<usize>> {
+    let (start, end) = match index {
+        LiteralValue::Range((start, end)) => (*start, *end),
+        LiteralValue::RangeEqual((start, end)) => (*start, end.saturating_add(1)),
+        _ => return Err(AstError::InvalidIndex(index.to_string()).into()),
+    };
+
+    match (usize::try_from(start), usize::try_from(end)) {
+        (Ok(start), Ok(end)) if start <= end && end <= len => Ok(start..end),
+        _ => Err(AstError::IndexOutOfBounds(index.to_string(), len).into()),
+    }
+}
+
+fn unwrap_as_i64(literal: Option<lexer::LiteralValue>) -> Result<i64> {
+    match literal {
+        Some(lexer::LiteralValue::Int(s)) => Ok(s),
+        _ => Err(AstError::UnwrapInt(literal).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic_type_errors() {
+        let values = vec![
+            LiteralValue::StringValue("a".to_owned()),
+            LiteralValue::Boolean(true),
+            LiteralValue::Range((0, 1)),
+            LiteralValue::RangeEqual((0, 1)),
+            LiteralValue::Null,
+        ];
+
+        for value in &values {
+            for other in values.iter().chain([LiteralValue::Int(1), LiteralValue::Float(1.)].iter()) {
+                // strings concatenate with strings and numbers
+                if !matches!((value, other), (LiteralValue::StringValue(_), LiteralValue::StringValue(_) | LiteralValue::Int(_) | LiteralValue::Float(_))) {
+                    assert!(value.checked_add(other).is_err());
+                }
+                assert!(other.checked_sub(value).is_err());
+                assert!(value.checked_mul(other).is_err());
+                assert!(other.checked_div(value).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn string_concatenation() {
+        let a = LiteralValue::StringValue("a".to_owned());
+        let concat = |x: &LiteralValue, y: &LiteralValue| x.checked_add(y).unwrap().to_string();
+
+        assert_eq!(concat(&a, &LiteralValue::StringValue("b".to_owned())), "ab");
+        assert_eq!(concat(&a, &LiteralValue::Int(1)), "a1");
+        assert_eq!(concat(&LiteralValue::Float(1.), &a), "1.0a");
+        assert_eq!(
+            LiteralValue::Boolean(true).checked_add(&a).unwrap_err().to_string(),
+            "Invalid operation: Boolean true + String \"a\""
+        );
+        assert_eq!(
+            LiteralValue::Int(1).compare(&a).unwrap_err().to_string(),
+            "Cannot compare Int 1 with String \"a\""
+        );
+        assert!(a.checked_add(&LiteralValue::Null).is_err());
+    }
+
+    #[test]
+    fn string_index_and_slice() {
+        let s = LiteralValue::StringValue("héllo".to_owned());
+        let index = |index: LiteralValue| s.index(&index).map(|value| value.to_string());
+
+        assert_eq!(index(LiteralValue::Int(1)).unwrap(), "é");
+        assert_eq!(index(LiteralValue::Range((1, 3))).unwrap(), "él");
+        assert_eq!(index(LiteralValue::RangeEqual((1, 4))).unwrap(), "éllo");
+        assert_eq!(index(LiteralValue::Range((5, 5))).unwrap(), "");
+        assert!(index(LiteralValue::Int(5)).is_err());
+        assert!(index(LiteralValue::Int(-1)).is_err());
+        assert!(index(LiteralValue::Range((3, 1))).is_err());
+        assert!(index(LiteralValue::RangeEqual((0, 5))).is_err());
+        assert!(index(LiteralValue::Float(1.)).is_err());
+        assert!(LiteralValue::Int(1).index(&LiteralValue::Int(0)).is_err());
+    }
+
+    #[test]
+    fn list_display() {
+        let list = LiteralValue::List(vec![LiteralValue::Int(1), LiteralValue::StringValue("a, b".to_owned())]);
+        assert_eq!(list.to_string(), "[1, \"a, b\"]");
+    }
+
+    #[test]
+    fn equals_across_types() {
+        assert!(LiteralValue::Int(1).equals(&LiteralValue::Float(1.)));
+        assert!(!LiteralValue::Int(1).equals(&LiteralValue::StringValue("1".to_owned())));
+        assert!(!LiteralValue::Null.equals(&LiteralValue::Boolean(false)));
+        assert!(LiteralValue::Null.equals(&LiteralValue::Null));
+    }
+}
@@ -1,13 +1,14 @@
 use thiserror::Error;
 
+pub mod callable;
 pub mod expr;
 pub mod literal;
 pub mod statement;
 pub mod environment;
 pub mod fps;
+pub mod runtime;
 
 use crate::lexer::{self, Token, TokenType};
-use literal::LiteralValue;
 
 #[derive(Error, Debug)]
 enum AstError {
@@ -17,18 +18,49 @@ enum AstError {
     UnwrapFloat(Option<lexer::LiteralValue>),
     #[error("Cannot compare against type '{0}'")]
     CompareTypes(String),
-    // #[error("Could not unwrap Lexer Literal Value as an usize: {0:?}")]
-    // UnwrapInt(Option<lexer::LiteralValue>),
+    #[error("Cannot compare {0} with {1}")]
+    Incomparable(String, String),
+    #[error("Could not unwrap Lexer Literal Value as an i64: {0:?}")]
+    UnwrapInt(Option<lexer::LiteralValue>),
     #[error("Could not create literal value from token: {0:?}")]
     LiteralValueCreate(Token),
     #[error("{0:?} not implemented for {1}")]
-    Unimplemented(TokenType, LiteralValue),
+    Unimplemented(TokenType, String),
     #[error("Unreacheble at evaluating expression: {0}")]
     Unreachable(String),
     #[error("Invalid operator: {0:?}")]
     InvalidOperator(TokenType),
-    #[error("Invalid operation: {0:?} {1} {2:?}")]
-    InvalidOperation(LiteralValue, String, LiteralValue),
+    // values are kept as their string representation as errors must be Send + Sync
+    #[error("Invalid operation: {0} {1} {2}")]
+    InvalidOperation(String, String, String),
+    #[error("Integer overflow: {0} {1} {2}")]
+    Overflow(String, String, String),
+    #[error("Integer overflow: {0}({1})")]
+    UnaryOverflow(String, String),
     #[error("Cannot divide by 0: {0}/{1}")]
-    Division0(LiteralValue, LiteralValue),
+    Division0(String, String),
+    #[error("Can only call functions, got '{0}' at line {1}")]
+    NotCallable(String, usize),
+    #[error("Cannot index into '{0}'")]
+    NotIndexable(String),
+    #[error("Index must be an integer or a range, got '{0}'")]
+    InvalidIndex(String),
+    #[error("Index {0} out of bounds for length {1}")]
+    IndexOutOfBounds(String, usize),
+    #[error("Range bounds must be integers, got '{0}'")]
+    RangeBound(String),
+    #[error("Expected a Range/RangeEqual to iterate over, got '{0}'")]
+    ExpectedRange(String),
+    #[error("'{0}' can only be used inside a for loop")]
+    NoIteration(String),
+    #[error("Frame duration must be a positive integer, got '{0}'")]
+    FpsDuration(String),
+    #[error("Function '{0}' expected {1} arguments but got {2} at line {3}")]
+    Arity(String, usize, usize, usize),
+    #[error("Call depth went over its limit of {0} calls, is a function recursing forever?")]
+    CallDepth(usize),
+    #[error("Function '{0}' cannot wait when called from an expression at line {1}, call it as a statement")]
+    WaitInExpression(String, usize),
+    #[error("Frame must be an integer from the current frame {1} on, got '{0}'")]
+    Frame(String, usize),
 }
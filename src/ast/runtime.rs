@@ -0,0 +1,34 @@
+use anyhow::Result;
+
+use super::{callable::NativeFunction, environment::Environment, fps::Suspended, literal::LiteralValue, statement::Statement};
+
+/// Control flow signal returned after interpreting a block
+#[derive(Debug)]
+pub enum Flow {
+    Next,
+    Return(LiteralValue),
+    /// `wait` suspended the block, the blocks left to resume are listed innermost first
+    Wait { frames: usize, suspended: Vec<Suspended> },
+}
+
+/// What running functions and expressions needs from the interpreter, so the syntax tree does not depend on it
+pub trait Runtime {
+    /// scope the running statements are executed in
+    fn environment(&self) -> &Environment;
+
+    /// index of the innermost `for` loop iteration being executed, referenced by `it`
+    fn iteration(&self) -> Option<usize>;
+
+    /// number of user function calls being executed
+    fn call_depth(&self) -> usize;
+
+    fn enter_call(&mut self);
+
+    fn exit_call(&mut self);
+
+    /// execute statements inside the given environment, restoring the current one afterwards
+    fn execute_block(&mut self, statements: Vec<Statement>, environment: Environment) -> Result<Flow>;
+
+    /// run the built-in a native function stands for, its arguments are already checked against its arity
+    fn call_native(&mut self, function: &NativeFunction, arguments: Vec<LiteralValue>) -> Result<LiteralValue>;
+}
@@ -1,17 +1,89 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::diagnostic::Span;
 use crate::lexer::Token;
 use super::expr::Expr;
 
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
-    Fps(Token),
+    /// `#N` or `#(expr)` frame marker, the expression is evaluated when its section starts
+    Fps { token: Token, duration: Option<Expr> },
     FpsEnd(Token),
+    /// `#*` starts the last section of the program, repeated on every frame until `exit()`
+    FpsForever(Token),
     Comment(Token),
     ArithmeticExpr(Expr),
-    Print(Expr),
+    /// `print` or `println`, several arguments are joined by spaces or formatted into a leading `{}` format string
+    Print { keyword: Token, arguments: Vec<Expr>, newline: bool },
     Declaration { id: Token, expr: Expr },
     Block {statements: Vec<Statement> },
     If {condition: Expr, then_block: Vec<Statement>, else_block: Option<Vec<Statement>> },
     For {expr: Expr, for_block: Vec<Statement> },
     While {condition: Expr, while_block: Vec<Statement> },
+    Function {name: Token, params: Vec<Token>, body: Vec<Statement> },
+    Return {keyword: Token, value: Option<Expr> },
+    /// `wait N;` suspends the enclosing blocks for N frames, `yield;` for one
+    Wait { keyword: Token, frames: Option<Expr> },
+    /// `every N as handle { .. }` runs its body every N frames, starting N frames after it is reached
+    Every { keyword: Token, frames: Expr, handle: Option<Token>, body: Vec<Statement> },
+    /// `at N as handle { .. }` runs its body once on frame N
+    At { keyword: Token, frame: Expr, handle: Option<Token>, body: Vec<Statement> },
+}
+
+// blocks are elided, statements are only displayed to give context to errors
+impl Display for Statement {
+    fn fmt(&self, format: &mut Formatter) -> fmt::Result {
+        match self {
+            Statement::Fps { token, duration: Some(duration) } => write!(format, "{}({})", token.lexeme, duration),
+            Statement::Fps { token, duration: None } => write!(format, "{}", token.lexeme),
+            Statement::FpsEnd(token) | Statement::FpsForever(token) => write!(format, "{}", token.lexeme),
+            Statement::Comment(token) => write!(format, "//{}", token.lexeme),
+            Statement::ArithmeticExpr(expr) => write!(format, "{};", expr),
+            Statement::Print { keyword, arguments, .. } => {
+                let arguments = arguments.iter().map(|argument| argument.to_string()).collect::<Vec<String>>();
+                write!(format, "{}({});", keyword.lexeme, arguments.join(", "))
+            }
+            Statement::Declaration { id, expr } => write!(format, "let {} = {};", id.lexeme, expr),
+            Statement::Block { .. } => write!(format, "{{ .. }}"),
+            Statement::If { condition, else_block: None, .. } => write!(format, "if {} {{ .. }}", condition),
+            Statement::If { condition, else_block: Some(_), .. } => write!(format, "if {} {{ .. }} else {{ .. }}", condition),
+            Statement::For { expr, .. } => write!(format, "for {} {{ .. }}", expr),
+            Statement::While { condition, .. } => write!(format, "while {} {{ .. }}", condition),
+            Statement::Function { name, params, .. } => {
+                let params = params.iter().map(|param| param.lexeme.clone()).collect::<Vec<String>>();
+                write!(format, "fn {}({}) {{ .. }}", name.lexeme, params.join(", "))
+            }
+            Statement::Return { value: Some(value), .. } => write!(format, "return {};", value),
+            Statement::Return { value: None, .. } => write!(format, "return;"),
+            Statement::Wait { keyword, frames: Some(frames) } => write!(format, "{} {};", keyword.lexeme, frames),
+            Statement::Wait { keyword, frames: None } => write!(format, "{};", keyword.lexeme),
+            Statement::Every { keyword, frames: frame, handle, .. } | Statement::At { keyword, frame, handle, .. } => match handle {
+                Some(handle) => write!(format, "{} {} as {} {{ .. }}", keyword.lexeme, frame, handle.lexeme),
+                None => write!(format, "{} {} {{ .. }}", keyword.lexeme, frame),
+            },
+        }
+    }
+}
+
+impl Statement {
+    /// source covered by the tokens of the statement header, bodies are not included
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Statement::Fps { token, duration } => Some(duration.as_ref().and_then(Expr::span).map_or(token.span(), |span| token.span().join(span))),
+            Statement::FpsEnd(token) | Statement::FpsForever(token) | Statement::Comment(token) => Some(token.span()),
+            Statement::ArithmeticExpr(expr) => expr.span(),
+            Statement::Print { keyword, arguments, .. } => Some(arguments.iter().filter_map(Expr::span).fold(keyword.span(), Span::join)),
+            Statement::Declaration { id, expr } => Some(expr.span().map_or(id.span(), |span| id.span().join(span))),
+            Statement::Block { .. } => None,
+            Statement::If { condition, .. } | Statement::While { condition, .. } => condition.span(),
+            Statement::For { expr, .. } => expr.span(),
+            Statement::Function { name, .. } => Some(name.span()),
+            Statement::Return { keyword, value } => Some(value.as_ref().and_then(Expr::span).map_or(keyword.span(), |span| keyword.span().join(span))),
+            Statement::Wait { keyword, frames } => Some(frames.as_ref().and_then(Expr::span).map_or(keyword.span(), |span| keyword.span().join(span))),
+            Statement::Every { keyword, frames: frame, .. } | Statement::At { keyword, frame, .. } => {
+                Some(frame.span().map_or(keyword.span(), |span| keyword.span().join(span)))
+            }
+        }
+    }
 }
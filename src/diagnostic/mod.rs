@@ -0,0 +1,378 @@
+use std::fmt::{self, Display, Formatter};
+use strum_macros::Display;
+
+/// contexts rendered at each end of a runtime error stack, a deep recursion would list the same ones over and over
+const STACK_EDGE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Severity {
+    Error,
+}
+
+/// Char offsets into the source, `end` is exclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// 1-based line and column of the start of the span
+    pub fn location(&self, source: &str) -> (usize, usize) {
+        let mut line = 1;
+        let mut column = 1;
+        for ch in source.chars().take(self.start) {
+            if ch == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        (line, column)
+    }
+
+    /// smallest span covering both spans
+    pub fn join(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+/// Error reported against a location of the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub expected: Option<String>,
+    pub found: Option<String>,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, format: &mut Formatter) -> fmt::Result {
+        write!(format, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl Diagnostic {
+    pub fn error(message: String, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+            span,
+            expected: None,
+            found: None,
+        }
+    }
+
+    pub fn expected(mut self, expected: String) -> Self {
+        self.expected = Some(expected);
+        self
+    }
+
+    pub fn found(mut self, found: String) -> Self {
+        self.found = Some(found);
+        self
+    }
+
+    /// render the diagnostic with the offending source line and a caret underline
+    ///
+    /// ```text
+    /// error: Expected ';' after statement
+    ///  --> main.fps:1:11
+    ///   |
+    /// 1 | let a = 1 print(a);
+    ///   |           ^^^^^ expected Semicolon, found 'print'
+    /// ```
+    pub fn render(&self, source: &str, name: &str) -> String {
+        let (line, column) = self.span.location(source);
+        let text = source.split('\n').nth(line - 1).unwrap_or("").trim_end_matches('\r');
+        let gutter = " ".repeat(line.to_string().len());
+
+        // underline at least one char, without going past the end of the line
+        let available = text.chars().count().saturating_sub(column - 1).max(1);
+        let width = self.span.end.saturating_sub(self.span.start).clamp(1, available);
+
+        let mut rendered = format!("{}: {}\n", self.severity, self.message);
+        rendered += &format!("{}--> {}:{}:{}\n", gutter, name, line, column);
+        rendered += &format!("{} |\n", gutter);
+        rendered += &format!("{} | {}\n", line, text);
+        rendered += &format!("{} | {}{}", gutter, " ".repeat(column - 1), "^".repeat(width));
+
+        match (&self.expected, &self.found) {
+            (Some(expected), Some(found)) => rendered += &format!(" expected {}, found {}", expected, found),
+            (Some(expected), None) => rendered += &format!(" expected {}", expected),
+            (None, Some(found)) => rendered += &format!(" found {}", found),
+            (None, None) => {}
+        }
+
+        rendered
+    }
+}
+
+/// Every diagnostic reported while compiling a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Display for Diagnostics {
+    fn fmt(&self, format: &mut Formatter) -> fmt::Result {
+        let messages = self.0.iter().map(|diagnostic| diagnostic.to_string()).collect::<Vec<String>>();
+        write!(format, "{}", messages.join("\n"))
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+impl Diagnostics {
+    pub fn render(&self, source: &str, name: &str) -> String {
+        self.0
+            .iter()
+            .map(|diagnostic| diagnostic.render(source, name))
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ContextKind {
+    Statement,
+    Expression,
+}
+
+/// Statement or expression that was being executed when a runtime error happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct Context {
+    pub kind: ContextKind,
+    pub description: String,
+    pub span: Option<Span>,
+}
+
+/// Error raised while interpreting, with the frame and the code that was executing
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    pub frame: Option<usize>,
+    /// program the failed task belongs to, a REPL session runs a program per input
+    pub program: Option<usize>,
+    /// location of the innermost expression or statement that failed
+    pub span: Option<Span>,
+    /// contexts the error went through, innermost first
+    pub stack: Vec<Context>,
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, format: &mut Formatter) -> fmt::Result {
+        write!(format, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl RuntimeError {
+    fn from_error(err: anyhow::Error) -> Self {
+        match err.downcast::<RuntimeError>() {
+            Ok(runtime) => runtime,
+            Err(err) => Self {
+                message: format!("{:#}", err),
+                frame: None,
+                program: None,
+                span: None,
+                stack: vec![],
+            },
+        }
+    }
+
+    /// record that the error went through the given statement or expression
+    ///
+    /// Only the innermost expression of a statement is kept, nested expressions would repeat it
+    pub fn context(err: anyhow::Error, kind: ContextKind, description: impl Fn() -> String, span: Option<Span>) -> anyhow::Error {
+        let mut runtime = Self::from_error(err);
+
+        let is_nested_expression = kind == ContextKind::Expression
+            && matches!(runtime.stack.last(), Some(context) if context.kind == ContextKind::Expression);
+        if !is_nested_expression {
+            runtime.stack.push(Context {
+                kind,
+                description: description(),
+                span,
+            });
+        }
+        if runtime.span.is_none() {
+            runtime.span = span;
+        }
+
+        runtime.into()
+    }
+
+    /// record the frame the error happened on
+    pub fn at_frame(err: anyhow::Error, frame: usize) -> anyhow::Error {
+        let mut runtime = Self::from_error(err);
+        runtime.frame.get_or_insert(frame);
+        runtime.into()
+    }
+
+    /// record the program the failed task belongs to
+    pub fn in_program(err: anyhow::Error, program: usize) -> anyhow::Error {
+        let mut runtime = Self::from_error(err);
+        runtime.program.get_or_insert(program);
+        runtime.into()
+    }
+
+    /// the error without its locations, for when its source is not at hand
+    fn without_location(&self) -> Self {
+        let stack = self.stack.iter().map(|context| Context { span: None, ..context.clone() }).collect();
+        Self {
+            span: None,
+            stack,
+            ..self.clone()
+        }
+    }
+
+    /// render the error with its source snippet, followed by the frame → statement → expression stack
+    pub fn render(&self, source: &str, name: &str) -> String {
+        let mut rendered = match self.span {
+            Some(span) => Diagnostic::error(self.message.clone(), span).render(source, name),
+            None => format!("{}: {}\n --> {}", Severity::Error, self.message, name),
+        };
+
+        if let Some(frame) = self.frame {
+            rendered += &format!("\n  = frame {}", frame);
+        }
+        let elided = self.stack.len().saturating_sub(2 * STACK_EDGE);
+        for (index, context) in self.stack.iter().rev().enumerate() {
+            if elided > 0 && index == STACK_EDGE {
+                rendered += &format!("\n  = ... {} more", elided);
+            }
+            if elided > 0 && (STACK_EDGE..STACK_EDGE + elided).contains(&index) {
+                continue;
+            }
+            rendered += &format!("\n  = in {} `{}`", context.kind, context.description);
+            if let Some(span) = context.span {
+                let (line, column) = span.location(source);
+                rendered += &format!(" at {}:{}", line, column);
+            }
+        }
+
+        rendered
+    }
+}
+
+/// render an error with its source snippet when it carries a location
+pub fn render_error(err: &anyhow::Error, source: &str, name: &str) -> String {
+    if let Some(diagnostics) = err.downcast_ref::<Diagnostics>() {
+        diagnostics.render(source, name)
+    } else if let Some(diagnostic) = err.downcast_ref::<Diagnostic>() {
+        diagnostic.render(source, name)
+    } else if let Some(runtime) = err.downcast_ref::<RuntimeError>() {
+        runtime.render(source, name)
+    } else {
+        format!("{}: {:#}", Severity::Error, err)
+    }
+}
+
+/// render an error of a REPL input, an error of a task left by an earlier input points into a source
+/// that is gone so its locations are left out
+pub fn render_input_error(err: &anyhow::Error, program: usize, source: &str, name: &str) -> String {
+    match err.downcast_ref::<RuntimeError>() {
+        Some(runtime) if runtime.program.is_some_and(|failed| failed != program) => runtime.without_location().render(source, name),
+        _ => render_error(err, source, name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_caret() {
+        let source = "let a = 1;\nlet b = 2 print(b);\n##";
+        let diagnostic = Diagnostic::error("Expected ';' after variable declaration".to_owned(), Span::new(21, 26))
+            .expected("Semicolon".to_owned())
+            .found("'print'".to_owned());
+
+        let expected = "error: Expected ';' after variable declaration
+ --> main.fps:2:11
+  |
+2 | let b = 2 print(b);
+  |           ^^^^^ expected Semicolon, found 'print'";
+
+        assert_eq!(diagnostic.render(source, "main.fps"), expected);
+    }
+
+    #[test]
+    fn render_end_of_input() {
+        let source = "print(1)";
+        let diagnostic = Diagnostic::error("Expected ';' after statement".to_owned(), Span::new(8, 8));
+
+        let expected = "error: Expected ';' after statement
+ --> main.fps:1:9
+  |
+1 | print(1)
+  |         ^";
+
+        assert_eq!(diagnostic.render(source, "main.fps"), expected);
+    }
+
+    #[test]
+    fn render_runtime_stack() {
+        let source = "let a = 0;\nprint(1 / a);\n##";
+        let err = RuntimeError::context(
+            anyhow::anyhow!("Cannot divide by 0: 1/0"),
+            ContextKind::Expression,
+            || "(/ 1 (var a))".to_owned(),
+            Some(Span::new(19, 22)),
+        );
+        let err = RuntimeError::context(err, ContextKind::Statement, || "print((/ 1 (var a)));".to_owned(), Some(Span::new(19, 22)));
+        let err = RuntimeError::at_frame(err, 1);
+
+        let expected = "error: Cannot divide by 0: 1/0
+ --> main.fps:2:9
+  |
+2 | print(1 / a);
+  |         ^^^
+  = frame 1
+  = in statement `print((/ 1 (var a)));` at 2:9
+  = in expression `(/ 1 (var a))` at 2:9";
+
+        assert_eq!(render_error(&err, source, "main.fps"), expected);
+    }
+
+    #[test]
+    fn render_earlier_input_error() {
+        let err = RuntimeError::context(anyhow::anyhow!("Cannot divide by 0: 1/0"), ContextKind::Statement, || "print((/ 1 (var q)));".to_owned(), Some(Span::new(10, 17)));
+        let err = RuntimeError::in_program(RuntimeError::at_frame(err, 3), 2);
+
+        // the error of a task left by an earlier input can't point into the current one
+        let expected = "error: Cannot divide by 0: 1/0
+ --> <repl>
+  = frame 3
+  = in statement `print((/ 1 (var q)));`";
+        assert_eq!(render_input_error(&err, 3, "let b = 2;", "<repl>"), expected);
+
+        assert!(render_input_error(&err, 2, "every 1 { print(1 / q); }", "<repl>").contains("1 | every 1 { print(1 / q); }"));
+    }
+
+    #[test]
+    fn render_deep_stack() {
+        let mut err = anyhow::anyhow!("Call depth went over its limit of 1000 calls");
+        for depth in 0..25 {
+            err = RuntimeError::context(err, ContextKind::Statement, || format!("call {}", depth), None);
+        }
+
+        let rendered = render_error(&err, "", "main.fps");
+        let lines = rendered.lines().skip(2).collect::<Vec<&str>>();
+
+        assert_eq!(lines.len(), 2 * STACK_EDGE + 1);
+        assert_eq!(lines[0], "  = in statement `call 24`");
+        assert_eq!(lines[STACK_EDGE], "  = ... 5 more");
+        assert_eq!(lines[2 * STACK_EDGE], "  = in statement `call 0`");
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use std::rc::Rc;
+use thiserror::Error;
+
+use super::Interpreter;
+use crate::ast::callable::NativeFunction;
+use crate::ast::environment::Environment;
+use crate::ast::literal::LiteralValue;
+
+#[derive(Error, Debug)]
+enum BuiltinError {
+    #[error("Function '{0}' expected {1} as argument {2}, got '{3}'")]
+    ArgumentType(&'static str, &'static str, usize, String),
+}
+
+/// Implementation of the native function of the same name
+struct Builtin {
+    name: &'static str,
+    arity: usize,
+    function: fn(&mut Interpreter, Vec<LiteralValue>) -> Result<LiteralValue>,
+}
+
+const BUILTINS: [Builtin; 12] = [
+    Builtin { name: "len", arity: 1, function: len },
+    Builtin { name: "upper", arity: 1, function: upper },
+    Builtin { name: "lower", arity: 1, function: lower },
+    Builtin { name: "trim", arity: 1, function: trim },
+    Builtin { name: "split", arity: 2, function: split },
+    Builtin { name: "contains", arity: 2, function: contains },
+    Builtin { name: "replace", arity: 3, function: replace },
+    Builtin { name: "frame", arity: 0, function: frame },
+    Builtin { name: "dt", arity: 0, function: dt },
+    Builtin { name: "elapsed", arity: 0, function: elapsed },
+    Builtin { name: "cancel", arity: 1, function: cancel },
+    Builtin { name: "exit", arity: 0, function: exit },
+];
+
+/// read-only scope holding the built-in functions, the global scope is a child of it so programs can shadow them
+pub fn prelude() -> Environment {
+    let prelude = Environment::new();
+    for Builtin { name, arity, .. } in BUILTINS {
+        prelude
+            .declare(name.to_owned(), LiteralValue::Native(Rc::new(NativeFunction { name, arity })))
+            .expect("built-in names are unique");
+    }
+    prelude.freeze();
+    prelude
+}
+
+/// run the built-in of a native function, only the prelude creates them
+pub fn call(interpreter: &mut Interpreter, function: &NativeFunction, arguments: Vec<LiteralValue>) -> Result<LiteralValue> {
+    let builtin = BUILTINS
+        .iter()
+        .find(|builtin| builtin.name == function.name)
+        .expect("native functions are built-ins");
+    (builtin.function)(interpreter, arguments)
+}
+
+fn string_argument<'a>(name: &'static str, arguments: &'a [LiteralValue], position: usize) -> Result<&'a str> {
+    match &arguments[position] {
+        LiteralValue::StringValue(val) => Ok(val),
+        value => Err(BuiltinError::ArgumentType(name, "a string", position + 1, value.to_string()).into()),
+    }
+}
+
+/// number of chars of a string or elements of a list
+fn len(_: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue> {
+    match &arguments[0] {
+        LiteralValue::StringValue(val) => Ok(LiteralValue::Int(val.chars().count() as i64)),
+        LiteralValue::List(values) => Ok(LiteralValue::Int(values.len() as i64)),
+        value => Err(BuiltinError::ArgumentType("len", "a string or a list", 1, value.to_string()).into()),
+    }
+}
+
+fn upper(_: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue> {
+    Ok(LiteralValue::StringValue(string_argument("upper", &arguments, 0)?.to_uppercase()))
+}
+
+fn lower(_: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue> {
+    Ok(LiteralValue::StringValue(string_argument("lower", &arguments, 0)?.to_lowercase()))
+}
+
+fn trim(_: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue> {
+    Ok(LiteralValue::StringValue(string_argument("trim", &arguments, 0)?.trim().to_owned()))
+}
+
+/// list of the parts of a string around a separator, an empty separator splits every char
+fn split(_: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue> {
+    let val = string_argument("split", &arguments, 0)?;
+    let separator = string_argument("split", &arguments, 1)?;
+
+    let parts = if separator.is_empty() {
+        val.chars().map(|ch| LiteralValue::StringValue(ch.to_string())).collect()
+    } else {
+        val.split(separator).map(|part| LiteralValue::StringValue(part.to_owned())).collect()
+    };
+
+    Ok(LiteralValue::List(parts))
+}
+
+/// whether a string contains a substring, or a list contains a value
+fn contains(_: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue> {
+    match &arguments[0] {
+        LiteralValue::List(values) => Ok(LiteralValue::Boolean(values.iter().any(|value| value.equals(&arguments[1])))),
+        _ => {
+            let val = string_argument("contains", &arguments, 0)?;
+            let pattern = string_argument("contains", &arguments, 1)?;
+            Ok(LiteralValue::Boolean(val.contains(pattern)))
+        }
+    }
+}
+
+fn replace(_: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue> {
+    let val = string_argument("replace", &arguments, 0)?;
+    let from = string_argument("replace", &arguments, 1)?;
+    let to = string_argument("replace", &arguments, 2)?;
+    Ok(LiteralValue::StringValue(val.replace(from, to)))
+}
+
+/// number of the frame being executed
+fn frame(interpreter: &mut Interpreter, _: Vec<LiteralValue>) -> Result<LiteralValue> {
+    Ok(LiteralValue::Int(interpreter.frame as i64))
+}
+
+/// seconds since the previous frame that ran started
+fn dt(interpreter: &mut Interpreter, _: Vec<LiteralValue>) -> Result<LiteralValue> {
+    Ok(LiteralValue::Float(interpreter.playback.dt().as_secs_f64()))
+}
+
+/// seconds since the first frame started
+fn elapsed(interpreter: &mut Interpreter, _: Vec<LiteralValue>) -> Result<LiteralValue> {
+    Ok(LiteralValue::Float(interpreter.playback.elapsed().as_secs_f64()))
+}
+
+/// stop the pending runs of an `every` or `at` block, false when it had none left
+fn cancel(interpreter: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue> {
+    match &arguments[0] {
+        LiteralValue::Timer(id) => Ok(LiteralValue::Boolean(interpreter.fps.cancel(*id))),
+        value => Err(BuiltinError::ArgumentType("cancel", "a timer handle", 1, value.to_string()).into()),
+    }
+}
+
+/// end the program once the statement calling it is done, nothing it scheduled on later frames runs
+fn exit(interpreter: &mut Interpreter, _: Vec<LiteralValue>) -> Result<LiteralValue> {
+    interpreter.exited = true;
+    Ok(LiteralValue::Null)
+}
@@ -0,0 +1,432 @@
+use anyhow::Result;
+use std::fmt::{self, Debug, Formatter};
+use std::io::{BufRead, Write};
+use thiserror::Error;
+
+use super::Interpreter;
+use crate::ast::expr::Expr;
+use crate::ast::fps::{section_bounds, Scheduled, Task};
+use crate::ast::literal::LiteralValue;
+use crate::ast::statement::Statement;
+use crate::lexer::FpsInput;
+use crate::parser::Parser;
+
+const PROMPT: &str = "(debug) ";
+
+const HELP: &str = "next, n               run to the start of the next frame
+step, s               run to the next statement
+continue, c           run to the next breakpoint
+break at frame <n>    pause when frame n starts
+break at line <l>     pause before the statements of line l
+watch <expr>          show the value of the expression at every pause
+print, p <expr>       show the value of the expression
+help, h               print this help
+quit, q               stop the program";
+
+#[derive(Error, Debug)]
+enum DebuggerError {
+    #[error("Unknown command '{0}', type 'help' for the list of commands")]
+    UnknownCommand(String),
+    #[error("Command '{0}' expects an expression")]
+    MissingExpression(&'static str),
+    #[error("Expected 'break at frame <n>' or 'break at line <l>', got '{0}'")]
+    Breakpoint(String),
+    #[error("Frame {0} already started, the current frame is {1}")]
+    PastFrame(usize, usize),
+    #[error("Program stopped by the debugger")]
+    Quit,
+}
+
+/// What the debugger pauses on, besides its breakpoints
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pause {
+    Frame,
+    Statement,
+    Breakpoint,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Breakpoint {
+    Frame(usize),
+    Line(usize),
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Next,
+    Step,
+    Continue,
+    Break(Breakpoint),
+    Watch(String),
+    Print(String),
+    Help,
+    Quit,
+}
+
+impl Command {
+    fn parse(input: &str) -> Result<Command> {
+        let (name, argument) = match input.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (input, ""),
+        };
+        let expression = |name: &'static str| -> Result<String> {
+            match argument {
+                "" => Err(DebuggerError::MissingExpression(name).into()),
+                argument => Ok(argument.to_owned()),
+            }
+        };
+
+        let command = match name {
+            "next" | "n" => Command::Next,
+            "step" | "s" => Command::Step,
+            "continue" | "c" => Command::Continue,
+            "break" | "b" => {
+                let words: Vec<&str> = argument.split_whitespace().collect();
+                let number = |word: &str| word.parse::<usize>().ok().filter(|number| *number > 0);
+                match words[..] {
+                    ["at", "frame", frame] if number(frame).is_some() => Command::Break(Breakpoint::Frame(number(frame).unwrap())),
+                    ["at", "line", line] if number(line).is_some() => Command::Break(Breakpoint::Line(number(line).unwrap())),
+                    _ => return Err(DebuggerError::Breakpoint(input.to_owned()).into()),
+                }
+            }
+            "watch" | "w" => Command::Watch(expression("watch")?),
+            "print" | "p" => Command::Print(expression("print")?),
+            "help" | "h" => Command::Help,
+            "quit" | "q" => Command::Quit,
+            _ => return Err(DebuggerError::UnknownCommand(name.to_owned()).into()),
+        };
+        Ok(command)
+    }
+}
+
+/// Pauses the program before frames and statements and reads commands to inspect it
+///
+/// Commands are read from `input` until one resumes the program, the end of the input lets the program run to its end
+pub struct Debugger {
+    // source of the program, to find the lines of the statements
+    source: String,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    pause: Pause,
+    breakpoints: Vec<Breakpoint>,
+    // expressions as entered, with their parsed form
+    watches: Vec<(String, Expr)>,
+    // an empty line repeats the last command
+    last: Option<String>,
+}
+
+impl Debug for Debugger {
+    fn fmt(&self, format: &mut Formatter) -> fmt::Result {
+        format
+            .debug_struct("Debugger")
+            .field("pause", &self.pause)
+            .field("breakpoints", &self.breakpoints)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Debugger {
+    /// debugger pausing before the first frame
+    pub fn new(source: &str, input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        Self {
+            source: source.to_owned(),
+            input,
+            output,
+            pause: Pause::Frame,
+            breakpoints: vec![],
+            watches: vec![],
+            last: None,
+        }
+    }
+
+    /// show the tasks scheduled on the frame about to run and the variables, if the debugger pauses there
+    pub(super) fn before_frame(&mut self, interpreter: &mut Interpreter) -> Result<()> {
+        let frame = interpreter.frame;
+        let breakpoint = self
+            .breakpoints
+            .iter()
+            .position(|breakpoint| matches!(breakpoint, Breakpoint::Frame(at) if *at <= frame));
+        // frames only move forward, a frame breakpoint is done once reached
+        if let Some(index) = breakpoint {
+            self.breakpoints.remove(index);
+        } else if self.pause == Pause::Breakpoint {
+            return Ok(());
+        }
+
+        writeln!(self.output, "frame {}", frame)?;
+        let tasks = interpreter.scheduled().get(&frame).into_iter().flatten();
+        for Scheduled { task, .. } in tasks {
+            writeln!(self.output, "    {}", task)?;
+            // a program task only names its section, list what the section runs
+            if let Task::Program { statements, position } = task {
+                let (start, end) = section_bounds(statements, *position);
+                for statement in &statements[start..end] {
+                    writeln!(self.output, "        {}", statement)?;
+                }
+            }
+        }
+        for (name, value) in interpreter.environment.variables() {
+            writeln!(self.output, "{} = {}", name, describe(&value))?;
+        }
+        self.prompt(interpreter)
+    }
+
+    /// show the line of the statement about to run, if the debugger pauses there
+    pub(super) fn before_statement(&mut self, interpreter: &mut Interpreter, statement: &Statement) -> Result<()> {
+        if matches!(statement, Statement::Comment(_)) {
+            return Ok(());
+        }
+        let Some(span) = statement.span() else {
+            return Ok(());
+        };
+        let (line, _) = span.location(&self.source);
+        if self.pause != Pause::Statement && !self.breakpoints.contains(&Breakpoint::Line(line)) {
+            return Ok(());
+        }
+
+        // output printed so far on the frame comes before the pause
+        self.output.write_all(&interpreter.output)?;
+        interpreter.output.clear();
+
+        let text = self.source.lines().nth(line - 1).unwrap_or_default().trim();
+        writeln!(self.output, "frame {}, line {}: {}", interpreter.frame, line, text)?;
+        self.prompt(interpreter)
+    }
+
+    /// read commands until one resumes the program
+    fn prompt(&mut self, interpreter: &mut Interpreter) -> Result<()> {
+        for (source, expr) in &self.watches {
+            writeln!(self.output, "watch {} = {}", source, evaluate(expr, interpreter))?;
+        }
+
+        loop {
+            write!(self.output, "{}", PROMPT)?;
+            self.output.flush()?;
+
+            let mut line = String::new();
+            // without commands the program runs to its end
+            if self.input.read_line(&mut line)? == 0 {
+                writeln!(self.output)?;
+                self.pause = Pause::Breakpoint;
+                self.breakpoints.clear();
+                return Ok(());
+            }
+            let line = match (line.trim(), &self.last) {
+                ("", Some(last)) => last.clone(),
+                ("", None) => continue,
+                (line, _) => line.to_owned(),
+            };
+            self.last = Some(line.clone());
+
+            let command = match Command::parse(&line) {
+                Ok(command) => command,
+                Err(err) => {
+                    writeln!(self.output, "error: {}", err)?;
+                    continue;
+                }
+            };
+            match command {
+                Command::Next => self.pause = Pause::Frame,
+                Command::Step => self.pause = Pause::Statement,
+                Command::Continue => self.pause = Pause::Breakpoint,
+                Command::Break(Breakpoint::Frame(frame)) if frame <= interpreter.frame => {
+                    writeln!(self.output, "error: {}", DebuggerError::PastFrame(frame, interpreter.frame))?;
+                    continue;
+                }
+                Command::Break(breakpoint) => {
+                    match breakpoint {
+                        Breakpoint::Frame(frame) => writeln!(self.output, "breakpoint at frame {}", frame)?,
+                        Breakpoint::Line(line) => writeln!(self.output, "breakpoint at line {}", line)?,
+                    }
+                    self.breakpoints.push(breakpoint);
+                    continue;
+                }
+                Command::Watch(source) => {
+                    match parse(&source) {
+                        Ok(expr) => {
+                            writeln!(self.output, "watch {} = {}", source, evaluate(&expr, interpreter))?;
+                            self.watches.push((source, expr));
+                        }
+                        Err(err) => writeln!(self.output, "error: {}", err)?,
+                    }
+                    continue;
+                }
+                Command::Print(source) => {
+                    match parse(&source) {
+                        Ok(expr) => writeln!(self.output, "{}", evaluate(&expr, interpreter))?,
+                        Err(err) => writeln!(self.output, "error: {}", err)?,
+                    }
+                    continue;
+                }
+                Command::Help => {
+                    writeln!(self.output, "{}", HELP)?;
+                    continue;
+                }
+                Command::Quit => return Err(DebuggerError::Quit.into()),
+            }
+            return Ok(());
+        }
+    }
+}
+
+fn parse(source: &str) -> Result<Expr> {
+    let mut scanner = FpsInput::new(source);
+    scanner.scan_tokens()?;
+    Parser::new(scanner.tokens).parse_expression()
+}
+
+/// value of the expression in the scope the program is paused in, or the error it fails with
+fn evaluate(expr: &Expr, interpreter: &mut Interpreter) -> String {
+    match expr.eval(interpreter) {
+        Ok(value) => describe(&value),
+        Err(err) => format!("error: {}", err),
+    }
+}
+
+/// strings are quoted to tell them apart from other values
+fn describe(value: &LiteralValue) -> String {
+    match value {
+        LiteralValue::StringValue(val) => format!("{:?}", val),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    use super::*;
+
+    /// output shared with the test once the debugger is handed to the interpreter
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// program output and debugger output of a debugged run
+    fn debug(input: &str, commands: &str) -> (String, String, Result<()>) {
+        let mut stdout = Vec::new();
+        let output = Shared::default();
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+        let statements = Parser::new(scanner.tokens).parse().unwrap();
+
+        let debugger = Debugger::new(input, Box::new(Cursor::new(commands.to_owned())), Box::new(output.clone()));
+        let mut interpreter = Interpreter::new().with_debugger(debugger);
+        let result = interpreter.interpret(&mut stdout, statements);
+
+        let debugged = String::from_utf8(output.0.borrow().clone()).unwrap();
+        (String::from_utf8(stdout).unwrap(), debugged, result)
+    }
+
+    #[test]
+    fn parse_commands() {
+        let inputs = vec![
+            ("n", Command::Next),
+            ("step", Command::Step),
+            ("break at frame 3", Command::Break(Breakpoint::Frame(3))),
+            ("b  at line 2", Command::Break(Breakpoint::Line(2))),
+            ("watch a + 1", Command::Watch("a + 1".to_owned())),
+            ("p len(s)", Command::Print("len(s)".to_owned())),
+        ];
+
+        for (input, expected) in inputs {
+            assert_eq!(Command::parse(input).unwrap(), expected);
+        }
+
+        assert_eq!(
+            Command::parse("break at 3").unwrap_err().to_string(),
+            "Expected 'break at frame <n>' or 'break at line <l>', got 'break at 3'"
+        );
+        assert_eq!(Command::parse("print").unwrap_err().to_string(), "Command 'print' expects an expression");
+        assert_eq!(
+            Command::parse("run").unwrap_err().to_string(),
+            "Unknown command 'run', type 'help' for the list of commands"
+        );
+    }
+
+    #[test]
+    fn frame_by_frame() {
+        let input = "let a = 1;\n#\nfor 0..2 {\n    println(it + a);\n}\n##";
+        let (stdout, debugged, result) = debug(input, "next\n\nprint a * 10\nnext\n");
+
+        result.unwrap();
+        assert_eq!(stdout, "FPS 2 -> 1\nFPS 3 -> 2\n");
+        assert_eq!(
+            debugged,
+            "frame 1
+    program start
+        let a = 1;
+(debug) frame 2
+    section #
+        for (.. 0 2) { .. }
+a = 1
+(debug) frame 3
+    iteration 1: println((+ it (var a)));
+a = 1
+(debug) 10
+(debug) "
+        );
+    }
+
+    #[test]
+    fn breakpoints_and_watches() {
+        let input = "let a = 0;\n#4\na = a + 1;\nprintln(a);\n##";
+        let commands = "break at line 4\nbreak at frame 4\nwatch a * 2\ncontinue\nc\nc\np b\nstep\nc\n";
+        let (stdout, debugged, result) = debug(input, commands);
+
+        result.unwrap();
+        assert_eq!(stdout, "FPS 2 -> 1\nFPS 3 -> 2\nFPS 4 -> 3\nFPS 5 -> 4\n");
+        assert_eq!(
+            debugged,
+            "frame 1
+    program start
+        let a = 0;
+(debug) breakpoint at line 4
+(debug) breakpoint at frame 4
+(debug) watch a * 2 = error: Variable 'a' is not yet declared in current scope
+(debug) frame 2, line 4: println(a);
+watch a * 2 = 2
+(debug) frame 3, line 4: println(a);
+watch a * 2 = 4
+(debug) frame 4
+    repeat: (a = (+ (var a) 1)); println((var a));
+a = 2
+watch a * 2 = 4
+(debug) error: Variable 'b' is not yet declared in current scope
+(debug) frame 4, line 3: a = a + 1;
+watch a * 2 = 4
+(debug) frame 4, line 4: println(a);
+watch a * 2 = 6
+(debug) \n"
+        );
+    }
+
+    #[test]
+    fn quit_stops_the_program() {
+        let (stdout, debugged, result) = debug("print(1);\n##", "break at frame 1\nstep\nquit\n");
+
+        assert_eq!(stdout, "");
+        assert_eq!(result.unwrap_err().to_string(), "Program stopped by the debugger");
+        assert_eq!(
+            debugged,
+            "frame 1
+    program start
+        print(1);
+(debug) error: Frame 1 already started, the current frame is 1
+(debug) frame 1, line 1: print(1);
+(debug) "
+        );
+    }
+}
@@ -1,130 +1,699 @@
+mod builtins;
+pub mod debugger;
+pub mod output;
+pub mod realtime;
+
+use std::collections::{BTreeMap, VecDeque};
 use std::io;
 use std::rc::Rc;
 
-use crate::ast::fps::Fps;
+use crate::ast::callable::{Function, NativeFunction};
+use crate::ast::expr::Expr;
+use crate::ast::fps::{section_bounds, Fps, FrameBlock, Iteration, Resume, Scheduled, Section, Suspended, Task, Timer};
 use crate::ast::literal::LiteralValue;
+use crate::ast::runtime::{Flow, Runtime};
 use crate::ast::{environment::Environment, statement::Statement};
+use crate::diagnostic::{ContextKind, RuntimeError};
+use crate::lexer::Token;
 
 use anyhow::Result;
+use debugger::Debugger;
+use output::{Printed, Sink, TextSink, DEFAULT_PREFIX};
+use realtime::{Clock, LateFrame, Playback, VirtualClock, DEFAULT_FPS};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+enum InterpreterError {
+    #[error("Format string has {0} placeholders but got {1} arguments")]
+    FormatArguments(usize, usize),
+    #[error("Frame ran over its budget of {0} instructions, is a loop running forever?")]
+    Budget(usize),
+    #[error("Program reached the limit of {0} frames")]
+    FrameLimit(usize),
+    #[error("Cannot schedule {0} frames after frame {1}, it is past the last frame")]
+    FrameOverflow(usize, usize),
+    #[error("Cannot spread a loop of {0} iterations over frames, it ends past the last frame")]
+    LoopOverflow(usize),
+}
+
+/// instructions a frame can run before it is aborted
+pub const DEFAULT_BUDGET: usize = 1_000_000;
+
+/// stack of the thread running a program, every nested call recurses through the interpreter
+/// so the default stack only fits a few hundred of them in debug builds
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
 
 #[derive(Debug)]
 pub struct Interpreter {
-    environment: Environment,
+    pub environment: Environment,
     fps: Fps,
+    frame: usize,
+    // indexes of the `for` loop iterations being executed, innermost last
+    iterations: Vec<usize>,
+    // number of consecutive frames the statements being executed are repeated on
+    span: usize,
+    // buffered output of the current frame, flushed into stdout by `interpret`
+    output: Vec<u8>,
+    sink: Box<dyn Sink>,
+    // paces frames, on simulated time unless running in real time
+    playback: Playback,
+    // called as soon as a frame overran its time slot
+    report_late_frame: fn(&LateFrame),
+    // statements and loop iterations run on the current frame, checked against the budget
+    instructions: usize,
+    budget: usize,
+    max_frames: Option<usize>,
+    // user function calls being executed, checked against `MAX_CALL_DEPTH`
+    call_depth: usize,
+    // set by `exit()`, nothing else of the running program runs once it is
+    exited: bool,
+    debugger: Option<Debugger>,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         Self {
-            environment: Environment::new(),
+            environment: builtins::prelude().child(),
             fps: Fps::new(),
+            frame: 0,
+            iterations: vec![],
+            span: 1,
+            output: vec![],
+            sink: Box::new(TextSink::prefixed(DEFAULT_PREFIX)),
+            playback: Playback::new(DEFAULT_FPS, Box::new(VirtualClock::default())),
+            report_late_frame: |_| {},
+            instructions: 0,
+            budget: DEFAULT_BUDGET,
+            max_frames: None,
+            call_depth: 0,
+            exited: false,
+            debugger: None,
+        }
+    }
+
+    /// abort frames that run more than `budget` statements and loop iterations
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// stop with an error instead of running past frame `max_frames`
+    pub fn with_max_frames(mut self, max_frames: usize) -> Self {
+        self.max_frames = Some(max_frames);
+        self
+    }
+
+    /// run `fps` frames per second, sleeping between frames on the given clock
+    pub fn with_fps(mut self, fps: u32, clock: Box<dyn Clock>) -> Self {
+        self.playback = Playback::new(fps, clock);
+        self
+    }
+
+    /// pause before frames and statements to inspect the program
+    pub fn with_debugger(mut self, debugger: Debugger) -> Self {
+        self.debugger = Some(debugger);
+        self
+    }
+
+    /// report the frames that overrun their time slot in real-time mode, while the program keeps running
+    pub fn with_late_frame_report(mut self, report: fn(&LateFrame)) -> Self {
+        self.report_late_frame = report;
+        self
+    }
+
+    /// write the output through another sink, instead of `FPS {frame} -> ` prefixed lines
+    pub fn with_sink(mut self, sink: Box<dyn Sink>) -> Self {
+        self.sink = sink;
+        self
+    }
+
+    fn interpret_block(&mut self, statements: Vec<Statement>) -> Result<Flow> {
+        for (index, statement) in statements.iter().enumerate() {
+            let flow = self
+                .interpret_statement(statement)
+                .map_err(|err| RuntimeError::context(err, ContextKind::Statement, || statement.to_string(), statement.span()))?;
+            if self.exited {
+                return Ok(Flow::Next);
+            }
+            match flow {
+                Flow::Next => {}
+                Flow::Return(_) => return Ok(flow),
+                Flow::Wait { frames, mut suspended } => {
+                    let rest = statements[index + 1..].to_vec();
+                    if !rest.is_empty() {
+                        suspended.push(self.suspended(Resume::Block(rest)));
+                    }
+                    return Ok(Flow::Wait { frames, suspended });
+                }
+            }
         }
+        Ok(Flow::Next)
     }
 
-    fn interpret_block(&mut self, frame: usize, stdout: &mut dyn io::Write, statements: Vec<Statement>) -> Result<()> {
-        for statement in statements {
-            match statement {
-                Statement::Fps(_) => {}
-                Statement::FpsEnd(_) => {}
-                Statement::Comment(_) => {}
-                Statement::ArithmeticExpr(expr) => {
-                    expr.eval(&mut self.environment)?;
+    fn interpret_statement(&mut self, statement: &Statement) -> Result<Flow> {
+        self.count_instruction()?;
+        self.debug(Some(statement))?;
+        match statement {
+            // frame markers are handled by `interpret_program`
+            Statement::Fps { .. } => {}
+            Statement::FpsEnd(_) => {}
+            Statement::FpsForever(_) => {}
+            Statement::Comment(_) => {}
+            Statement::ArithmeticExpr(expr) => return expr.execute(self),
+            Statement::Print { keyword, arguments, newline } => {
+                let mut values: Vec<LiteralValue> = vec![];
+                for argument in arguments {
+                    values.push(argument.eval(self)?);
                 }
-                Statement::Print(expr) => {
-                    let value = expr.eval(&mut self.environment)?;
-                    writeln!(stdout, "FPS {} -> {}", frame, value).unwrap();
+                let text = format_values(&values)?;
+                let printed = Printed {
+                    frame: self.frame,
+                    line: keyword.line,
+                    values: &values,
+                    text: &text,
+                    newline: *newline,
+                };
+                self.sink.print(&mut self.output, &printed)?;
+            }
+            Statement::Declaration { id, expr } => {
+                let value = expr.eval(self)?;
+                self.environment.declare(id.lexeme.clone(), value)?;
+            }
+            Statement::Block {
+                statements: block_statements,
+            } => {
+                return self.execute_block(block_statements.clone(), self.environment.child());
+            }
+            Statement::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let cond = condition.eval(self)?;
+
+                if cond.is_true()? == LiteralValue::Boolean(true) {
+                    return self.execute_block(then_block.clone(), self.environment.child());
+                } else if let Some(else_block) = else_block {
+                    return self.execute_block(else_block.clone(), self.environment.child());
                 }
-                Statement::Declaration { id, expr } => {
-                    let value = expr.eval(&mut self.environment)?;
-                    self.environment.declare(id.lexeme, value)?;
-                    // println!("{value}");
+            }
+            Statement::For { expr, for_block } => {
+                let range = expr.eval(self)?;
+                let iterations = Fps::get_iterations_from_range(&range)?;
+                // iteration i runs i * span frames after the loop is reached, so every frame runs the body once
+                let Some(span) = self.span.checked_mul(iterations) else {
+                    return Err(InterpreterError::LoopOverflow(iterations).into());
+                };
+
+                // only the next iteration is scheduled, it schedules the one after it when it runs
+                if iterations > 1 {
+                    let iteration = Iteration {
+                        index: 1,
+                        end: iterations,
+                        step: self.span,
+                        block: FrameBlock {
+                            statements: for_block.clone(),
+                            iterations: self.iterations.clone(),
+                            span,
+                            environment: Some(self.environment.clone()),
+                        },
+                    };
+                    self.fps.schedule(self.frame_after(self.span)?, Task::Iteration(iteration));
                 }
-                Statement::Block {
-                    statements: block_statements,
-                } => {
-                    let mut new_env = Environment::new();
-                    new_env.parent = Some(Rc::from(self.environment.clone()));
-                    // println!("new_env {:?}", new_env);
-                    let old_env = self.environment.clone();
-                    // println!("old_env {:?}", old_env);
-                    // println!("new_env {:?}", new_env);
-                    self.environment = new_env;
-                    self.interpret_block(frame, stdout, block_statements)?;
-                    // println!("old_env {:?}", old_env);
-                    self.environment = old_env;
+
+                if iterations > 0 {
+                    return self.interpret_iteration(for_block.clone(), 0, span);
                 }
-                Statement::If {
-                    condition,
-                    then_block,
-                    else_block,
-                } => {
-                    let cond = condition.eval(&mut self.environment)?;
-
-                    if cond.is_true()? == LiteralValue::Boolean(true) {
-                        self.interpret_block(frame, stdout, then_block)?;
-                    } else if let Some(else_block) = else_block {
-                        self.interpret_block(frame, stdout, else_block)?;
-                    }
+            }
+            Statement::While { condition, while_block } => return self.interpret_while(condition, while_block),
+            Statement::Function { name, params, body } => {
+                let function = Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    closure: self.environment.clone(),
+                };
+                self.environment.declare(name.lexeme.clone(), LiteralValue::Function(Rc::new(function)))?;
+            }
+            Statement::Return { keyword: _, value } => {
+                let value = match value {
+                    Some(expr) => expr.eval(self)?,
+                    None => LiteralValue::Null,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Statement::Wait { keyword: _, frames } => {
+                let frames = match frames {
+                    Some(expr) => Fps::get_fps_duration_from_value(&expr.eval(self)?)?,
+                    None => 1,
+                };
+                return Ok(Flow::Wait { frames, suspended: vec![] });
+            }
+            Statement::Every {
+                keyword: _,
+                frames,
+                handle,
+                body,
+            } => {
+                let interval = Fps::get_fps_duration_from_value(&frames.eval(self)?)?;
+                self.schedule_timer(self.frame_after(interval)?, Some(interval), handle, body)?;
+            }
+            Statement::At {
+                keyword: _,
+                frame,
+                handle,
+                body,
+            } => {
+                let frame = Fps::get_frame_from_value(&frame.eval(self)?, self.frame)?;
+                self.schedule_timer(frame, None, handle, body)?;
+            }
+        };
+        Ok(Flow::Next)
+    }
+
+    /// hand the interpreter to the debugger before a statement runs, or before the frame starts without one
+    fn debug(&mut self, statement: Option<&Statement>) -> Result<()> {
+        // expressions evaluated by the debugger don't pause again
+        let Some(mut debugger) = self.debugger.take() else {
+            return Ok(());
+        };
+        let result = match statement {
+            Some(statement) => debugger.before_statement(self, statement),
+            None => debugger.before_frame(self),
+        };
+        self.debugger = Some(debugger);
+        result
+    }
+
+    /// count an instruction against the frame budget
+    fn count_instruction(&mut self) -> Result<()> {
+        self.instructions += 1;
+        if self.instructions > self.budget {
+            return Err(InterpreterError::Budget(self.budget).into());
+        }
+        Ok(())
+    }
+
+    fn interpret_while(&mut self, condition: &Expr, body: &[Statement]) -> Result<Flow> {
+        // iterations are counted too, so a loop with an empty body still runs out of budget
+        while !self.exited && condition.eval(self)?.is_true()? == LiteralValue::Boolean(true) {
+            self.count_instruction()?;
+            match self.execute_block(body.to_vec(), self.environment.child())? {
+                Flow::Next => {}
+                Flow::Return(value) => return Ok(Flow::Return(value)),
+                // the condition is checked again once the body resumed
+                Flow::Wait { frames, mut suspended } => {
+                    suspended.push(self.suspended(Resume::While {
+                        condition: condition.clone(),
+                        body: body.to_vec(),
+                    }));
+                    return Ok(Flow::Wait { frames, suspended });
                 }
-                Statement::For { expr: _, for_block: _ } => {
-                    panic!("ERROR - For loops are not yet supported inside other statements (because of FPS logic which needs to be implemented)")
-
-                    // let range = range.eval(&mut self.environment)?;
-                    // let mut for_blocks: Vec<Statement> = vec![];
-                    // match range {
-                    //     LiteralValue::Range((start, end)) => {
-                    //         let range = start..end;
-                    //         for _ in range {
-                    //             for_blocks.extend(for_block.clone());
-                    //         }
-                    //     }
-                    //     LiteralValue::RangeEqual((start, end)) => {
-                    //         let range = start..=end;
-                    //         for _ in range {
-                    //             for_blocks.extend(for_block.clone());
-                    //         }
-                    //     }
-                    //     _ => panic!(),
-                    // }
-
-                    // self.interpret_block(frame, stdout, for_blocks)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// capture the state a block runs in, to resume it on a later frame
+    fn suspended(&self, kind: Resume) -> Suspended {
+        Suspended {
+            kind,
+            environment: self.environment.clone(),
+            iterations: self.iterations.clone(),
+            span: self.span,
+        }
+    }
+
+    /// continue blocks suspended by `wait`, from the innermost one outwards
+    fn resume(&mut self, suspended: Vec<Suspended>) -> Result<Flow> {
+        let mut pending = suspended.into_iter();
+        while let Some(block) = pending.next() {
+            if self.exited {
+                return Ok(Flow::Next);
+            }
+            let previous_iterations = std::mem::replace(&mut self.iterations, block.iterations);
+            let previous_span = std::mem::replace(&mut self.span, block.span);
+            let flow = match block.kind {
+                Resume::Block(statements) => self.execute_block(statements, block.environment),
+                Resume::While { condition, body } => {
+                    let previous = std::mem::replace(&mut self.environment, block.environment);
+                    let flow = self.interpret_while(&condition, &body);
+                    self.environment = previous;
+                    flow
                 }
-                Statement::While { condition, while_block } => {
-                    let mut cond = condition.eval(&mut self.environment)?;
+                Resume::Call => Ok(Flow::Next),
+            };
+            self.span = previous_span;
+            self.iterations = previous_iterations;
 
-                    while cond.is_true()? == LiteralValue::Boolean(true) {
-                        self.interpret_block(frame, stdout, while_block.clone())?;
-                        cond = condition.eval(&mut self.environment)?;
+            match flow? {
+                Flow::Next => {}
+                // the function returns to the blocks it was called from, its value is dropped like any statement call
+                Flow::Return(_) => {
+                    if !pending.any(|block| matches!(block.kind, Resume::Call)) {
+                        return Ok(Flow::Next);
                     }
                 }
+                Flow::Wait { frames, mut suspended } => {
+                    suspended.extend(pending);
+                    return Ok(Flow::Wait { frames, suspended });
+                }
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// schedule the blocks suspended by a `wait` on the frame they resume on
+    fn suspend(&mut self, flow: Flow) -> Result<()> {
+        if let Flow::Wait { frames, suspended } = flow {
+            self.fps.schedule(self.frame_after(frames)?, Task::Resume(suspended));
+        }
+        Ok(())
+    }
+
+    /// frame `frames` after the current one, frames are counted in a `usize`
+    fn frame_after(&self, frames: usize) -> Result<usize> {
+        match self.frame.checked_add(frames) {
+            Some(frame) => Ok(frame),
+            None => Err(InterpreterError::FrameOverflow(frames, self.frame).into()),
+        }
+    }
+
+    /// schedule the first run of an `every` or `at` block and declare its handle
+    fn schedule_timer(&mut self, frame: usize, interval: Option<usize>, handle: &Option<Token>, body: &[Statement]) -> Result<()> {
+        let id = self.fps.timer_id();
+        if let Some(handle) = handle {
+            self.environment.declare(handle.lexeme.clone(), LiteralValue::Timer(id))?;
+        }
+
+        let block = FrameBlock {
+            statements: body.to_vec(),
+            iterations: self.iterations.clone(),
+            span: 1,
+            environment: Some(self.environment.clone()),
+        };
+        self.fps.schedule(frame, Task::Timer(Timer { id, interval, block }));
+        Ok(())
+    }
+
+    fn interpret_iteration(&mut self, statements: Vec<Statement>, iteration: usize, span: usize) -> Result<Flow> {
+        self.iterations.push(iteration);
+        let previous_span = std::mem::replace(&mut self.span, span);
+        let flow = self.execute_block(statements, self.environment.child());
+        self.span = previous_span;
+        self.iterations.pop();
+        flow
+    }
+
+    fn interpret_frame_block(&mut self, block: FrameBlock) -> Result<Flow> {
+        let previous_iterations = std::mem::replace(&mut self.iterations, block.iterations);
+        let previous_span = std::mem::replace(&mut self.span, block.span);
+        let flow = match block.environment {
+            Some(environment) => self.execute_block(block.statements, environment),
+            None => self.interpret_block(block.statements),
+        };
+        self.span = previous_span;
+        self.iterations = previous_iterations;
+        // a `return` inside a scheduled loop iteration only ends that iteration
+        match flow? {
+            Flow::Return(_) => Ok(Flow::Next),
+            flow => Ok(flow),
+        }
+    }
+
+    fn flush(&mut self, stdout: &mut dyn io::Write) -> Result<()> {
+        stdout.write_all(&self.output)?;
+        self.output.clear();
+        Ok(())
+    }
+
+    /// start the section of the program at `position` on the current frame
+    fn interpret_program(&mut self, statements: Vec<Statement>, position: usize) -> Result<()> {
+        // the duration is evaluated when the section starts, so it can depend on values computed by previous frames
+        let duration = match statements.get(position) {
+            Some(marker @ Statement::Fps { .. }) => self
+                .section_duration(marker)
+                .map_err(|err| RuntimeError::context(err, ContextKind::Statement, || marker.to_string(), marker.span()))?,
+            // `#*` and the statements before the first marker run on a single frame
+            _ => 1,
+        };
+
+        self.interpret_section(Section {
+            statements,
+            position,
+            index: 0,
+            duration,
+        })
+    }
+
+    /// run a frame of a section, then schedule its next frame or the next section on the following frame
+    ///
+    /// Scheduling one frame at a time, like timers and loop iterations do, keeps the tasks repeating over
+    /// frames in the order they first ran in. Blocks suspended by `wait` on the last frame resume before the next section
+    fn interpret_section(&mut self, section: Section) -> Result<()> {
+        let (start, end) = section_bounds(&section.statements, section.position);
+        let flow = self.interpret_frame_block(FrameBlock {
+            statements: section.statements[start..end].to_vec(),
+            iterations: vec![],
+            span: section.duration,
+            environment: None,
+        })?;
+        self.suspend(flow)?;
+
+        if section.index + 1 < section.duration {
+            let next = Section {
+                index: section.index + 1,
+                ..section
             };
+            self.fps.schedule(self.frame_after(1)?, Task::Section(next));
+        } else if let Some(Statement::Fps { .. } | Statement::FpsForever(_)) = section.statements.get(end) {
+            // `##` ends the program
+            let statements = section.statements;
+            self.fps.schedule(self.frame_after(1)?, Task::Program { statements, position: end });
+        } else if let Some(Statement::FpsForever(_)) = section.statements.get(section.position) {
+            // the open-ended section starts over on the next frame
+            let Section { statements, position, .. } = section;
+            self.fps.schedule(self.frame_after(1)?, Task::Program { statements, position });
         }
         Ok(())
     }
 
-    pub fn interpret(&mut self, stdout: &mut dyn io::Write, statements: Vec<Statement>) -> Result<()> {
-        self.fps.allocate_statements_to_frame(&mut self.environment, statements)?;
-        // println!("frames {:?}", self.fps.frames);
+    fn section_duration(&mut self, marker: &Statement) -> Result<usize> {
+        match marker {
+            Statement::Fps { token, duration: None } => Fps::get_fps_duration_from_token(token),
+            Statement::Fps {
+                token: _,
+                duration: Some(expr),
+            } => {
+                let value = expr.eval(self)?;
+                Fps::get_fps_duration_from_value(&value)
+            }
+            _ => Ok(1),
+        }
+    }
 
-        for (frame, range_statements) in self.fps.frames.clone() {
-            // println!("frame {} statements: {:?}", frame, range_statements);
-            for statement in range_statements {
-                // println!("{:?}", statement);
-                // match statement {
-                //     Statement::For { range, for_block } => todo!(),
-                //     _ => self.interpret_block(frame, stdout, vec![statement])?,
-                // }
+    fn interpret_task(&mut self, task: Task) -> Result<()> {
+        match task {
+            Task::Program { statements, position } => self.interpret_program(statements, position),
+            Task::Section(section) => self.interpret_section(section),
+            Task::Resume(suspended) => {
+                let flow = self.resume(suspended)?;
+                self.suspend(flow)?;
+                Ok(())
+            }
+            Task::Timer(timer) => {
+                // the next run is scheduled first so the body can cancel it
+                if let Some(interval) = timer.interval {
+                    self.fps.schedule(self.frame_after(interval)?, Task::Timer(timer.clone()));
+                }
+
+                // every run gets its own scope, nested in the one the block was reached from
+                let block = FrameBlock {
+                    environment: timer.block.environment.as_ref().map(Environment::child),
+                    ..timer.block
+                };
+                let flow = self.interpret_frame_block(block)?;
+                self.suspend(flow)?;
+                Ok(())
+            }
+            Task::Iteration(iteration) => {
+                if iteration.index + 1 < iteration.end {
+                    let next = Iteration {
+                        index: iteration.index + 1,
+                        ..iteration.clone()
+                    };
+                    self.fps.schedule(self.frame_after(iteration.step)?, Task::Iteration(next));
+                }
 
-                self.interpret_block(frame, stdout, vec![statement])?
+                // every iteration runs in its own scope, nested in the one the loop was reached from
+                let mut block = FrameBlock {
+                    environment: iteration.block.environment.as_ref().map(Environment::child),
+                    ..iteration.block
+                };
+                block.iterations.push(iteration.index);
+                let flow = self.interpret_frame_block(block)?;
+                self.suspend(flow)?;
+                Ok(())
             }
         }
+    }
+
+    /// run an input of a REPL session from the frame after the previous input ended
+    ///
+    /// Every input gets its own scope nested in the previous ones, so it can declare a variable again.
+    /// The `every` blocks left once it is done keep their frames, they run along with the next input
+    pub fn interpret_input(&mut self, stdout: &mut dyn io::Write, statements: Vec<Statement>) -> Result<()> {
+        self.schedule_input(statements)?;
+        self.run(stdout)
+    }
 
+    /// schedule an input of a REPL session without running it, see `interpret_input`
+    pub fn schedule_input(&mut self, statements: Vec<Statement>) -> Result<()> {
+        self.environment = self.environment.child();
+        self.schedule(statements)
+    }
+
+    pub fn interpret(&mut self, stdout: &mut dyn io::Write, statements: Vec<Statement>) -> Result<()> {
+        self.schedule(statements)?;
+        self.run(stdout)?;
+        // nothing resumes the `every` blocks of a program run on its own, they end with it
+        self.fps.drop_program();
+        Ok(())
+    }
+
+    /// schedule a program on the frame after the current one
+    fn schedule(&mut self, statements: Vec<Statement>) -> Result<()> {
+        let frame = self.frame_after(1)?;
+        self.fps.start_program();
+        self.fps.schedule(frame, Task::Program { statements, position: 0 });
+        self.playback.start(frame);
+        Ok(())
+    }
+
+    // `every` blocks run until nothing else is left, a REPL session resumes them with its next input
+    fn run(&mut self, stdout: &mut dyn io::Write) -> Result<()> {
+        while !self.fps.is_idle() {
+            self.run_frame(stdout)?;
+        }
         Ok(())
     }
+
+    /// run the next frame with scheduled tasks, `None` when nothing is scheduled
+    pub fn run_frame(&mut self, stdout: &mut dyn io::Write) -> Result<Option<usize>> {
+        let Some(frame) = self.fps.next_frame() else {
+            return Ok(None);
+        };
+        if let Some(max_frames) = self.max_frames.filter(|max_frames| frame > *max_frames) {
+            // the program of the first task past the limit ends, the ones of other REPL inputs reach it in turn
+            self.fps.next_task(frame);
+            self.fps.drop_program();
+            let err = RuntimeError::at_frame(InterpreterError::FrameLimit(max_frames).into(), max_frames);
+            return Err(RuntimeError::in_program(err, self.fps.program()));
+        }
+
+        self.frame = frame;
+        self.instructions = 0;
+        self.playback.wait_for(frame);
+        if let Err(err) = self.debug(None) {
+            self.fps.frames.clear();
+            return Err(RuntimeError::at_frame(err, frame));
+        }
+        // tasks schedule new tasks on later frames, or later on the current one, while executing
+        while let Some(task) = self.fps.next_task(frame) {
+            let result = self.interpret_task(task);
+            if result.is_err() {
+                // drop the rest of the failed program, the ones of other REPL inputs keep running
+                self.fps.drop_program();
+                self.sink.end_frame(&mut self.output)?;
+            }
+            self.flush(stdout)?;
+            result.map_err(|err| RuntimeError::in_program(RuntimeError::at_frame(err, frame), self.fps.program()))?;
+            // `exit()` drops what is left of the program calling it, the ones of other REPL inputs keep running
+            if self.exited {
+                self.fps.drop_program();
+                self.exited = false;
+            }
+        }
+        self.sink.end_frame(&mut self.output)?;
+        self.flush(stdout)?;
+        if let Some(late) = self.playback.finish(frame) {
+            (self.report_late_frame)(&late);
+        }
+
+        Ok(Some(frame))
+    }
+
+    /// tasks waiting in the frame queue, by frame
+    pub fn scheduled(&self) -> &BTreeMap<usize, VecDeque<Scheduled>> {
+        &self.fps.frames
+    }
+
+    /// id of the last program scheduled, a REPL session schedules one per input
+    pub fn program(&self) -> usize {
+        self.fps.last_program()
+    }
+}
+
+impl Runtime for Interpreter {
+    fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    fn iteration(&self) -> Option<usize> {
+        self.iterations.last().copied()
+    }
+
+    fn call_depth(&self) -> usize {
+        self.call_depth
+    }
+
+    fn enter_call(&mut self) {
+        self.call_depth += 1;
+    }
+
+    fn exit_call(&mut self) {
+        self.call_depth -= 1;
+    }
+
+    fn execute_block(&mut self, statements: Vec<Statement>, environment: Environment) -> Result<Flow> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let flow = self.interpret_block(statements);
+        self.environment = previous;
+        flow
+    }
+
+    fn call_native(&mut self, function: &NativeFunction, arguments: Vec<LiteralValue>) -> Result<LiteralValue> {
+        builtins::call(self, function, arguments)
+    }
+}
+
+/// text printed for the arguments of `print`/`println`
+///
+/// A leading string with `{}` placeholders is formatted with the remaining arguments, otherwise they are joined by spaces
+fn format_values(values: &[LiteralValue]) -> Result<String> {
+    if let [LiteralValue::StringValue(template), arguments @ ..] = values {
+        let placeholders = template.matches("{}").count();
+        if placeholders > 0 && !arguments.is_empty() {
+            if placeholders != arguments.len() {
+                return Err(InterpreterError::FormatArguments(placeholders, arguments.len()).into());
+            }
+
+            let mut pieces = template.split("{}");
+            let mut formatted = pieces.next().unwrap_or_default().to_owned();
+            for (argument, piece) in arguments.iter().zip(pieces) {
+                formatted += &argument.to_string();
+                formatted += piece;
+            }
+            return Ok(formatted);
+        }
+    }
+
+    Ok(values.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(" "))
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::diagnostic::Span;
     use crate::{lexer::FpsInput, parser::Parser};
 
     use super::*;
@@ -146,4 +715,871 @@ mod tests {
 
         assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
     }
+
+    #[test]
+    fn function_call() {
+        let mut stdout = Vec::new();
+
+        let input = "fn fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); } print(fib(10)); ##";
+        let expected = "FPS 1 -> 55\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn function_arity() {
+        let mut stdout = Vec::new();
+
+        let input = "fn add(a, b) { return a + b; } print(add(1)); ##";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        let result = interpreter.interpret(&mut stdout, statements);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Function 'add' expected 2 arguments but got 1 at line 1"
+        )
+    }
+
+    #[test]
+    fn for_loop_iteration() {
+        let mut stdout = Vec::new();
+
+        let input = "for 0..3 { print(it); } ##";
+        let expected = "FPS 1 -> 0\nFPS 2 -> 1\nFPS 3 -> 2\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn nested_for_loops() {
+        let mut stdout = Vec::new();
+
+        let input = "for 0..2 { if true { for 0..2 { print(it); } } } ##";
+        let expected = "FPS 1 -> 0\nFPS 2 -> 0\nFPS 3 -> 1\nFPS 4 -> 1\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn runtime_frame_duration() {
+        let mut stdout = Vec::new();
+
+        let input = "let n = 1; #2 n = n + 1; #(n) print(n); ##";
+        let expected = "FPS 4 -> 3\nFPS 5 -> 3\nFPS 6 -> 3\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn for_loop_runtime_range() {
+        let mut stdout = Vec::new();
+
+        let input = "let n = 1; # n = n + 1; # for -1..n { print(it); } ##";
+        let expected = "FPS 3 -> 0\nFPS 4 -> 1\nFPS 5 -> 2\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn block_assigns_outer_variable() {
+        let mut stdout = Vec::new();
+
+        let input = "let a = 2; { a = 3; } print(a); ##";
+        let expected = "FPS 1 -> 3\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn closure_shares_scope() {
+        let mut stdout = Vec::new();
+
+        let input = "fn counter() { let c = 0; fn inc() { c = c + 1; return c; } return inc; } let f = counter(); f(); print(f()); ##";
+        let expected = "FPS 1 -> 2\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn runtime_error_context() {
+        let mut stdout = Vec::new();
+
+        let input = "let a = 0; # print(1 / a); ##";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        let result = interpreter.interpret(&mut stdout, statements);
+
+        let err = result.unwrap_err().downcast::<RuntimeError>().unwrap();
+        assert_eq!(err.message, "Cannot divide by 0: 1/0");
+        assert_eq!(err.frame, Some(2));
+        assert_eq!(err.span, Some(Span::new(21, 24)));
+        assert_eq!(
+            err.stack.iter().map(|context| context.description.as_str()).collect::<Vec<&str>>(),
+            vec!["(/ 1 (var a))", "print((/ 1 (var a)));"]
+        );
+    }
+
+    #[test]
+    fn int_and_float_arithmetic() {
+        let mut stdout = Vec::new();
+
+        let input = "println(7 / 2); println(7 / 2.); println(1 + 0.5); println(1 == 1.0); ##";
+        let expected = "FPS 1 -> 3\nFPS 1 -> 3.5\nFPS 1 -> 1.5\nFPS 1 -> true\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn int_overflow() {
+        let inputs = vec![
+            ("let a = 9223372036854775807; print(a + 1); ##", "Integer overflow: 9223372036854775807 + 1"),
+            ("println(-(-9223372036854775807 - 1)); ##", "Integer overflow: -(-9223372036854775808)"),
+        ];
+
+        for (input, error) in inputs {
+            let mut stdout = Vec::new();
+
+            let mut scanner = FpsInput::new(input);
+            scanner.scan_tokens().unwrap();
+
+            let mut parser = Parser::new(scanner.tokens);
+            let statements = parser.parse().unwrap();
+
+            let mut interpreter: Interpreter = Interpreter::new();
+            let result = interpreter.interpret(&mut stdout, statements);
+
+            assert_eq!(result.unwrap_err().to_string(), error);
+        }
+    }
+
+    #[test]
+    fn invalid_operation_is_recoverable() {
+        let mut stdout = Vec::new();
+
+        let input = "print(true + 1); ##";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        let result = interpreter.interpret(&mut stdout, statements);
+
+        assert_eq!(result.unwrap_err().to_string(), "Invalid operation: Boolean true + Int 1")
+    }
+
+    #[test]
+    fn string_concatenation_and_indexing() {
+        let mut stdout = Vec::new();
+
+        let input = r#"let s = "frame " + 1; println(s + "!"); println(s[0] + s[6..len(s)]); println(s[0..=4]); ##"#;
+        let expected = "FPS 1 -> frame 1!\nFPS 1 -> f1\nFPS 1 -> frame\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn string_interpolation() {
+        let mut stdout = Vec::new();
+
+        let input = r#"let a = 1; # for 0..2 { a = a + 1; print("a={a} it={it} ok={a > 1}\t\"{upper("x")}\""); } ##"#;
+        let expected = "FPS 2 -> a=2 it=0 ok=true\t\"X\"\nFPS 3 -> a=3 it=1 ok=true\t\"X\"\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn string_builtins() {
+        let mut stdout = Vec::new();
+
+        let input = r#"
+            let s = "  Hello World ";
+            println(len(s));
+            println(upper(trim(s)) + lower(" AB"));
+            println(split(trim(s), " ")[1]);
+            println(split("ab", ""));
+            println(contains(s, "World") && !contains(split("a,b", ","), "c"));
+            println(replace(s, "World", "fps"));
+            ##"#;
+        let expected = "FPS 1 -> 14\nFPS 1 -> HELLO WORLD ab\nFPS 1 -> World\nFPS 1 -> [\"a\", \"b\"]\nFPS 1 -> true\nFPS 1 ->   Hello fps \n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn builtin_errors() {
+        let cases = [
+            ("print(upper(1)); ##", "Function 'upper' expected a string as argument 1, got '1'"),
+            (r#"print(len("a", "b")); ##"#, "Function 'len' expected 1 arguments but got 2 at line 1"),
+            (r#"print("abc"[3]); ##"#, "Index 3 out of bounds for length 3"),
+        ];
+
+        for (input, message) in cases {
+            let mut stdout = Vec::new();
+
+            let mut scanner = FpsInput::new(input);
+            scanner.scan_tokens().unwrap();
+
+            let mut parser = Parser::new(scanner.tokens);
+            let statements = parser.parse().unwrap();
+
+            let mut interpreter: Interpreter = Interpreter::new();
+            let result = interpreter.interpret(&mut stdout, statements);
+
+            assert_eq!(result.unwrap_err().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn print_and_println() {
+        let mut stdout = Vec::new();
+
+        let input = r#"print("a", 1); print("b"); println(); println("c", [1], 2.); # print("open"); ##"#;
+        let expected = "FPS 1 -> a 1b\nFPS 1 -> c [1] 2.0\nFPS 2 -> open\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn format_string() {
+        let mut stdout = Vec::new();
+
+        let input = r#"let a = 1; println("{} of {}!", a, "b"); println("{}"); println("{}", "{}"); print("{} {}", 1); ##"#;
+        let expected = "[1] 1 of b!\n[1] {}\n[1] {}\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new().with_sink(Box::new(TextSink::prefixed("[{frame}] ")));
+        let result = interpreter.interpret(&mut stdout, statements);
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected);
+        let err = result.unwrap_err().downcast::<RuntimeError>().unwrap();
+        assert_eq!(err.message, "Format string has 2 placeholders but got 1 arguments");
+        // literal arguments don't point to the source, the error is located at the keyword
+        let start = input.rfind("print").unwrap();
+        assert_eq!(err.span, Some(Span::new(start, start + 5)));
+    }
+
+    #[test]
+    fn json_lines_output() {
+        let mut stdout = Vec::new();
+
+        let input = "let a = 1;\n# println(a);\nprint(a, 2.);\n##";
+        let expected = r#"{"frame":2,"line":2,"type":"Int","value":1}
+{"frame":2,"line":3,"type":"String","value":"1 2.0"}
+"#;
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new().with_sink(Box::new(output::JsonLinesSink));
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn real_time_frames() {
+        let mut stdout = Vec::new();
+
+        // frames 2 and 3 have no tasks but still take their time
+        let input = "print(1); #2 # print(4); ##";
+        let expected = "FPS 1 -> 1\nFPS 4 -> 4\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let clock = VirtualClock::default();
+        let mut interpreter: Interpreter = Interpreter::new()
+            .with_fps(20, Box::new(clock.clone()))
+            .with_late_frame_report(|late| panic!("no frame should be late, got {}", late));
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected);
+        assert_eq!(clock.now(), std::time::Duration::from_millis(150));
+    }
+
+    #[test]
+    fn frame_clock_builtins() {
+        let mut stdout = Vec::new();
+
+        let input = "println(frame(), dt(), elapsed()); #2 println(frame()); # println(frame(), dt(), elapsed()); ##";
+        let expected = "FPS 1 -> 1 0.0 0.0\nFPS 2 -> 2\nFPS 3 -> 3\nFPS 4 -> 4 0.1 0.3\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new().with_fps(10, Box::new(VirtualClock::default()));
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn builtins_are_read_only() {
+        let mut stdout = Vec::new();
+
+        let input = "let len = 1; println(len); frame = 2; ##";
+        let expected = "FPS 1 -> 1\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        let result = interpreter.interpret(&mut stdout, statements);
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected);
+        assert_eq!(result.unwrap_err().to_string(), "Cannot assign to built-in 'frame'");
+    }
+
+    #[test]
+    fn wait_in_while() {
+        let mut stdout = Vec::new();
+
+        let input = "let i = 0; while i < 3 { println(i); i = i + 1; wait 2; } println(\"done\"); ##";
+        let expected = "FPS 1 -> 0\nFPS 3 -> 1\nFPS 5 -> 2\nFPS 7 -> done\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn wait_in_function() {
+        let mut stdout = Vec::new();
+
+        let input = "fn f(n) { if n > 0 { println(\"before\"); yield; println(\"after\"); return; } println(\"never\"); } f(1); println(\"back\"); # println(\"section\"); ##";
+        let expected = "FPS 1 -> before\nFPS 2 -> after\nFPS 2 -> back\nFPS 2 -> section\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn wait_keeps_iteration() {
+        let mut stdout = Vec::new();
+
+        let input = "for 0..2 { println(it); wait 3; println(it); } ##";
+        let expected = "FPS 1 -> 0\nFPS 2 -> 1\nFPS 4 -> 0\nFPS 5 -> 1\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn wait_in_expression_call() {
+        let mut stdout = Vec::new();
+
+        let input = "fn f() { wait 1; return 1; } let x = f(); ##";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        let result = interpreter.interpret(&mut stdout, statements);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Function 'f' cannot wait when called from an expression at line 1, call it as a statement"
+        );
+    }
+
+    #[test]
+    fn every_until_cancelled() {
+        let mut stdout = Vec::new();
+
+        let input = "let n = 0; every 2 as tick { n = n + 1; println(n); if n == 3 { cancel(tick); } } #9 ##";
+        let expected = "FPS 3 -> 1\nFPS 5 -> 2\nFPS 7 -> 3\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn every_ends_with_program() {
+        let mut stdout = Vec::new();
+
+        let input = "every 3 { println(frame()); } at 2 { println(\"at\", frame()); } #7 ##";
+        let expected = "FPS 2 -> at 2\nFPS 4 -> 4\nFPS 7 -> 7\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected);
+        assert!(interpreter.scheduled().is_empty());
+    }
+
+    #[test]
+    fn timer_and_section_order() {
+        let mut stdout = Vec::new();
+
+        // the timer ran first on frame 2, so it runs before the section on every frame after it
+        let input = "let x = 0; every 1 { x = x + 1; println(\"e\", x); } #3 println(\"p\", x); ##";
+        let expected = "FPS 2 -> e 1\nFPS 2 -> p 1\nFPS 3 -> e 2\nFPS 3 -> p 2\nFPS 4 -> e 3\nFPS 4 -> p 3\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn timer_errors() {
+        let inputs = vec![
+            ("# # at 1 { print(1); } ##", "Frame must be an integer from the current frame 2 on, got '1'"),
+            ("every 0 { print(1); } ##", "Frame duration must be a positive integer, got '0'"),
+            ("cancel(1); ##", "Function 'cancel' expected a timer handle as argument 1, got '1'"),
+        ];
+
+        for (input, error) in inputs {
+            let mut stdout = Vec::new();
+
+            let mut scanner = FpsInput::new(input);
+            scanner.scan_tokens().unwrap();
+
+            let mut parser = Parser::new(scanner.tokens);
+            let statements = parser.parse().unwrap();
+
+            let mut interpreter: Interpreter = Interpreter::new();
+            let result = interpreter.interpret(&mut stdout, statements);
+
+            assert_eq!(result.unwrap_err().to_string(), error);
+        }
+    }
+
+    #[test]
+    fn forever_until_exit() {
+        let mut stdout = Vec::new();
+
+        let input = "let n = 0; #* n = n + 1; println(n); if n == 3 { exit(); println(\"never\"); } ##";
+        let expected = "FPS 2 -> 1\nFPS 3 -> 2\nFPS 4 -> 3\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.interpret(&mut stdout, statements).unwrap();
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn frame_limit() {
+        let mut stdout = Vec::new();
+
+        let input = "#* println(frame());";
+        let expected = "FPS 1 -> 1\nFPS 2 -> 2\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new().with_max_frames(2);
+        let result = interpreter.interpret(&mut stdout, statements);
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected);
+        assert_eq!(result.unwrap_err().to_string(), "Program reached the limit of 2 frames");
+    }
+
+    #[test]
+    fn instruction_budget() {
+        let mut stdout = Vec::new();
+
+        let input = "println(1); # let i = 0; while i < 10 { i = i + 1; } println(i); # while true {} ##";
+        let expected = "FPS 1 -> 1\nFPS 2 -> 10\n";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        // the second frame runs 23 instructions, the budget is per frame
+        let mut interpreter: Interpreter = Interpreter::new().with_budget(25);
+        let result = interpreter.interpret(&mut stdout, statements);
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Frame ran over its budget of 25 instructions, is a loop running forever?"
+        );
+    }
+
+    #[test]
+    fn long_loops() {
+        // iterations are scheduled one at a time, so the frame limit stops the loop right away
+        let input = "for 0..=9223372036854775807 { } ##";
+        let mut stdout = Vec::new();
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new().with_max_frames(10);
+        let err = interpreter.interpret(&mut stdout, statements).unwrap_err();
+        assert_eq!(err.to_string(), "Program reached the limit of 10 frames");
+
+        let input = "for 0..9223372036854775807 { for 0..4 { } } ##";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        let err = interpreter.interpret(&mut stdout, statements).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot spread a loop of 4 iterations over frames, it ends past the last frame");
+    }
+
+    #[test]
+    fn frame_overflow() {
+        let inputs = vec![
+            "wait 9223372036854775807; wait 9223372036854775807; wait 9223372036854775807; ##",
+            "wait 9223372036854775807; wait 9223372036854775807; every 1 { println(1); } ##",
+        ];
+
+        for input in inputs {
+            let mut stdout = Vec::new();
+
+            let mut scanner = FpsInput::new(input);
+            scanner.scan_tokens().unwrap();
+
+            let mut parser = Parser::new(scanner.tokens);
+            let statements = parser.parse().unwrap();
+
+            let mut interpreter: Interpreter = Interpreter::new();
+            let result = interpreter.interpret(&mut stdout, statements);
+
+            let err = result.unwrap_err().downcast::<RuntimeError>().unwrap();
+            assert!(err.message.starts_with("Cannot schedule "), "{}", err.message);
+            assert!(err.message.ends_with(" frames after frame 18446744073709551615, it is past the last frame"));
+            assert_eq!(err.frame, Some(usize::MAX));
+        }
+    }
+
+    #[test]
+    fn call_depth_limit() {
+        let input = "fn f(n) { if n == 0 { return 0; } return f(n - 1) + 1; } print(f(999)); fn g(n) { return g(n + 1); } g(0); ##";
+        let expected = "FPS 1 -> 999\n";
+
+        // like the CLI, the program runs on a thread with a stack deep enough for the limit
+        let runner = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+            let mut scanner = FpsInput::new(input);
+            scanner.scan_tokens().unwrap();
+
+            let mut parser = Parser::new(scanner.tokens);
+            let statements = parser.parse().unwrap();
+
+            let mut stdout = Vec::new();
+            let mut interpreter: Interpreter = Interpreter::new();
+            let result = interpreter.interpret(&mut stdout, statements);
+            (String::from_utf8(stdout).unwrap(), result.map_err(|err| err.to_string()), interpreter.call_depth())
+        });
+        let (stdout, result, depth) = runner.unwrap().join().unwrap();
+
+        assert_eq!(stdout, expected);
+        assert_eq!(
+            result.unwrap_err(),
+            "Call depth went over its limit of 1000 calls, is a function recursing forever?"
+        );
+        assert_eq!(depth, 0);
+    }
+
+    #[test]
+    fn session_inputs() {
+        let mut stdout = Vec::new();
+
+        let inputs = [
+            "let a = 1; every 2 as tick { println(\"tick\", a); }",
+            "let a = a + 1; for 0..3 { println(it); }",
+            "cancel(tick); println(a, frame());",
+            "println(\"done\");",
+        ];
+        // the timer keeps the `a` of the scope it was declared in, and runs before the input reaching its frame
+        let expected = "FPS 2 -> 0\nFPS 3 -> tick 1\nFPS 3 -> 1\nFPS 4 -> 2\nFPS 5 -> tick 1\nFPS 5 -> 2 5\nFPS 6 -> done\n";
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        for input in inputs {
+            let mut scanner = FpsInput::new(input);
+            scanner.scan_tokens().unwrap();
+
+            let mut parser = Parser::new(scanner.tokens);
+            let statements = parser.parse_incremental().unwrap();
+
+            interpreter.interpret_input(&mut stdout, statements).unwrap();
+        }
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected)
+    }
+
+    #[test]
+    fn session_exit() {
+        let mut stdout = Vec::new();
+
+        let inputs = [
+            "let n = 0; every 2 as tick { n = n + 1; println(\"tick\", n); }",
+            "wait 2; exit(); println(\"never\");",
+            "wait 2; cancel(tick);",
+        ];
+        // `exit()` only ends the second input, the timer of the first one keeps running
+        let expected = "FPS 3 -> tick 1\nFPS 5 -> tick 2\nFPS 7 -> tick 3\n";
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        for input in inputs {
+            let mut scanner = FpsInput::new(input);
+            scanner.scan_tokens().unwrap();
+
+            let mut parser = Parser::new(scanner.tokens);
+            let statements = parser.parse_incremental().unwrap();
+
+            interpreter.interpret_input(&mut stdout, statements).unwrap();
+        }
+
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), expected);
+        assert!(interpreter.scheduled().is_empty());
+    }
+
+    #[test]
+    fn session_timer_input() {
+        let mut stdout = Vec::new();
+        let mut interpreter: Interpreter = Interpreter::new();
+
+        let mut input = |source: &str, stdout: &mut Vec<u8>| {
+            let mut scanner = FpsInput::new(source);
+            scanner.scan_tokens().unwrap();
+
+            let mut parser = Parser::new(scanner.tokens);
+            let statements = parser.parse_incremental().unwrap();
+
+            interpreter.interpret_input(stdout, statements).unwrap();
+        };
+
+        // an input leaving only an `every` block is done, the block runs along with the next input
+        input("every 1 { println(\"tick\", frame()); }", &mut stdout);
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), "");
+
+        input("wait 2;", &mut stdout);
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), "FPS 2 -> tick 2\nFPS 3 -> tick 3\nFPS 4 -> tick 4\n");
+        assert_eq!(interpreter.scheduled().len(), 1);
+    }
+
+    #[test]
+    fn session_failed_task() {
+        let mut stdout = Vec::new();
+
+        let inputs = ["let q = 0;", "every 1 { println(1 / q); }", "let b = 2;", "println(b);"];
+        let mut results = vec![];
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        for input in inputs {
+            let mut scanner = FpsInput::new(input);
+            scanner.scan_tokens().unwrap();
+
+            let mut parser = Parser::new(scanner.tokens);
+            let statements = parser.parse_incremental().unwrap();
+
+            results.push(interpreter.interpret_input(&mut stdout, statements));
+        }
+
+        // the timer of the second input fails while the third one runs, only the timer is dropped
+        let err = results.remove(2).unwrap_err().downcast::<RuntimeError>().unwrap();
+        assert_eq!(err.message, "Cannot divide by 0: 1/0");
+        assert_eq!(err.program, Some(2));
+        assert!(results.into_iter().all(|result| result.is_ok()));
+        assert_eq!(interpreter.program(), 4);
+        assert!(interpreter.scheduled().is_empty());
+        assert_eq!(std::str::from_utf8(&stdout).unwrap(), "FPS 4 -> 2\n")
+    }
 }
@@ -0,0 +1,159 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::fmt::Debug;
+use std::io::Write;
+
+use crate::ast::literal::LiteralValue;
+
+/// prefix written at the start of every output line, `{frame}` is replaced by the current frame
+pub const DEFAULT_PREFIX: &str = "FPS {frame} -> ";
+
+/// Output of a `print`/`println` statement
+#[derive(Debug)]
+pub struct Printed<'a> {
+    pub frame: usize,
+    /// source line of the statement
+    pub line: usize,
+    /// evaluated arguments
+    pub values: &'a [LiteralValue],
+    /// arguments joined or formatted as they are printed
+    pub text: &'a str,
+    pub newline: bool,
+}
+
+/// Destination format of the printed values
+pub trait Sink: Debug {
+    fn print(&mut self, out: &mut dyn Write, printed: &Printed) -> Result<()>;
+
+    /// the current frame is over, or stopped by an error
+    fn end_frame(&mut self, _out: &mut dyn Write) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Human readable lines, each starting with an optional prefix
+///
+/// `print` leaves the line open for the next print of the frame, lines never span frames
+#[derive(Debug)]
+pub struct TextSink {
+    prefix: String,
+    line_open: bool,
+}
+
+impl TextSink {
+    pub fn prefixed(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_owned(),
+            line_open: false,
+        }
+    }
+
+    /// values only
+    pub fn plain() -> Self {
+        Self::prefixed("")
+    }
+}
+
+impl Sink for TextSink {
+    fn print(&mut self, out: &mut dyn Write, printed: &Printed) -> Result<()> {
+        if !self.line_open {
+            write!(out, "{}", self.prefix.replace("{frame}", &printed.frame.to_string()))?;
+        }
+        write!(out, "{}", printed.text)?;
+        if printed.newline {
+            writeln!(out)?;
+        }
+        self.line_open = !printed.newline;
+        Ok(())
+    }
+
+    fn end_frame(&mut self, out: &mut dyn Write) -> Result<()> {
+        if self.line_open {
+            writeln!(out)?;
+            self.line_open = false;
+        }
+        Ok(())
+    }
+}
+
+/// One JSON record per print, `{"frame", "line", "value", "type"}`
+///
+/// A single argument keeps its type, several arguments are recorded as the printed string
+#[derive(Debug)]
+pub struct JsonLinesSink;
+
+impl Sink for JsonLinesSink {
+    fn print(&mut self, out: &mut dyn Write, printed: &Printed) -> Result<()> {
+        let (value, value_type) = match printed.values {
+            [value] => (to_json(value), value.type_name()),
+            _ => (json!(printed.text), "String"),
+        };
+        let record = json!({
+            "frame": printed.frame,
+            "line": printed.line,
+            "value": value,
+            "type": value_type,
+        });
+        writeln!(out, "{}", record)?;
+        Ok(())
+    }
+}
+
+fn to_json(value: &LiteralValue) -> Value {
+    match value {
+        LiteralValue::Int(val) => json!(val),
+        LiteralValue::Float(val) => json!(val),
+        LiteralValue::StringValue(val) => json!(val),
+        LiteralValue::Boolean(val) => json!(val),
+        LiteralValue::List(values) => Value::Array(values.iter().map(to_json).collect()),
+        LiteralValue::Null => Value::Null,
+        // ranges and functions are recorded as they are printed
+        _ => json!(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printed<'a>(values: &'a [LiteralValue], text: &'a str, newline: bool) -> Printed<'a> {
+        Printed {
+            frame: 2,
+            line: 3,
+            values,
+            text,
+            newline,
+        }
+    }
+
+    #[test]
+    fn text_lines() {
+        let mut out: Vec<u8> = vec![];
+        let mut sink = TextSink::prefixed("[{frame}] ");
+
+        sink.print(&mut out, &printed(&[], "a", false)).unwrap();
+        sink.print(&mut out, &printed(&[], "b", true)).unwrap();
+        sink.print(&mut out, &printed(&[], "c", false)).unwrap();
+        sink.end_frame(&mut out).unwrap();
+        sink.end_frame(&mut out).unwrap();
+
+        assert_eq!(std::str::from_utf8(&out).unwrap(), "[2] ab\n[2] c\n");
+    }
+
+    #[test]
+    fn json_records() {
+        let mut out: Vec<u8> = vec![];
+        let mut sink = JsonLinesSink;
+
+        let list = [LiteralValue::List(vec![LiteralValue::Int(1), LiteralValue::StringValue("\"a\"".to_owned())])];
+        sink.print(&mut out, &printed(&list, "[1, \"\\\"a\\\"\"]", true)).unwrap();
+        let values = [LiteralValue::Float(1.5), LiteralValue::Null];
+        sink.print(&mut out, &printed(&values, "1.5 Null", false)).unwrap();
+        sink.end_frame(&mut out).unwrap();
+
+        let expected = r#"{"frame":2,"line":3,"type":"List","value":[1,"\"a\""]}
+{"frame":2,"line":3,"type":"String","value":"1.5 Null"}
+"#;
+        assert_eq!(std::str::from_utf8(&out).unwrap(), expected);
+    }
+}
@@ -0,0 +1,221 @@
+use std::cell::Cell;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// frame rate of the simulated time frames run on outside of real-time mode
+pub const DEFAULT_FPS: u32 = 60;
+
+/// Source of time for real-time playback
+pub trait Clock: Debug {
+    /// time elapsed since the clock started
+    fn now(&self) -> Duration;
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// Wall clock
+#[derive(Debug)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self { start: Instant::now() }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Simulated clock that only moves when slept on, clones share the same time
+///
+/// Frames paced on it run back-to-back, with deterministic timestamps
+#[derive(Debug, Clone, Default)]
+pub struct VirtualClock {
+    now: Rc<Cell<Duration>>,
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+/// Frame that ran past the end of its time slot
+#[derive(Debug, Clone, PartialEq)]
+pub struct LateFrame {
+    pub frame: usize,
+    /// time from the start of the frame slot to the end of the frame
+    pub took: Duration,
+    pub budget: Duration,
+    /// number of frame slots skipped to catch up
+    pub dropped: usize,
+}
+
+impl Display for LateFrame {
+    fn fmt(&self, format: &mut Formatter) -> fmt::Result {
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.;
+        write!(
+            format,
+            "frame {} took {:.1}ms, over its {:.1}ms budget ({} frames dropped)",
+            self.frame,
+            millis(self.took),
+            millis(self.budget),
+            self.dropped
+        )
+    }
+}
+
+/// Paces frames so they start on a fixed grid of time slots
+///
+/// Frames are never skipped, a late frame shifts the grid so the following frames are not rushed to catch up
+#[derive(Debug)]
+pub struct Playback {
+    clock: Box<dyn Clock>,
+    budget: Duration,
+    /// time and frame the grid of slots starts at
+    anchor: (Duration, usize),
+    /// time the first frame started at
+    origin: Option<Duration>,
+    /// start times of the previous and the current frame
+    previous_start: Option<Duration>,
+    current_start: Duration,
+}
+
+impl Playback {
+    pub fn new(fps: u32, clock: Box<dyn Clock>) -> Self {
+        let anchor = (clock.now(), 0);
+        Self {
+            clock,
+            budget: Duration::from_secs(1) / fps.max(1),
+            anchor,
+            origin: None,
+            previous_start: None,
+            current_start: Duration::ZERO,
+        }
+    }
+
+    /// start the grid at the given frame, now
+    pub fn start(&mut self, frame: usize) {
+        let now = self.clock.now();
+        self.anchor = (now, frame);
+        self.origin.get_or_insert(now);
+    }
+
+    fn slot_start(&self, frame: usize) -> Duration {
+        let (time, anchor_frame) = self.anchor;
+        let slots = u32::try_from(frame.saturating_sub(anchor_frame)).unwrap_or(u32::MAX);
+        time.saturating_add(self.budget.saturating_mul(slots))
+    }
+
+    /// sleep until the slot of the frame starts, frames without tasks still take their slot
+    pub fn wait_for(&mut self, frame: usize) {
+        let start = self.slot_start(frame);
+        let now = self.clock.now();
+        if now < start {
+            self.clock.sleep(start - now);
+        }
+        self.previous_start = Some(self.current_start);
+        self.current_start = self.clock.now();
+    }
+
+    /// time from the start of the first frame to the start of the current one
+    pub fn elapsed(&self) -> Duration {
+        self.current_start.saturating_sub(self.origin.unwrap_or(self.current_start))
+    }
+
+    /// time from the start of the previous frame that ran to the start of the current one, zero on the first frame
+    pub fn dt(&self) -> Duration {
+        match (self.origin, self.previous_start) {
+            (Some(origin), Some(previous)) => self.current_start.saturating_sub(previous.max(origin)),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// end the frame, it is returned as late when it overran its slot
+    pub fn finish(&mut self, frame: usize) -> Option<LateFrame> {
+        let start = self.slot_start(frame);
+        let now = self.clock.now();
+        let end = start + self.budget;
+        if now <= end {
+            return None;
+        }
+
+        let overrun = now - end;
+        let dropped = overrun.as_nanos().div_ceil(self.budget.as_nanos()) as usize;
+        // the next frame starts right away instead of rushing through the missed slots
+        self.anchor = (now, frame.saturating_add(1));
+        Some(LateFrame {
+            frame,
+            took: now - start,
+            budget: self.budget,
+            dropped,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_start_on_their_slot() {
+        let mut clock = VirtualClock::default();
+        let mut playback = Playback::new(10, Box::new(clock.clone()));
+
+        playback.start(1);
+        playback.wait_for(1);
+        assert_eq!(clock.now(), Duration::ZERO);
+        assert_eq!(playback.dt(), Duration::ZERO);
+        clock.sleep(Duration::from_millis(30));
+        assert_eq!(playback.finish(1), None);
+
+        // frame 2 has no tasks but still takes its slot
+        playback.wait_for(3);
+        assert_eq!(clock.now(), Duration::from_millis(200));
+        assert_eq!(playback.elapsed(), Duration::from_millis(200));
+        assert_eq!(playback.dt(), Duration::from_millis(200));
+        assert_eq!(playback.finish(3), None);
+    }
+
+    #[test]
+    fn late_frames_shift_the_grid() {
+        let mut clock = VirtualClock::default();
+        let mut playback = Playback::new(10, Box::new(clock.clone()));
+
+        playback.start(1);
+        playback.wait_for(1);
+        clock.sleep(Duration::from_millis(250));
+        let late = playback.finish(1).unwrap();
+
+        // the next frame starts right away
+        playback.wait_for(2);
+        assert_eq!(clock.now(), Duration::from_millis(250));
+        assert_eq!(playback.finish(2), None);
+        playback.wait_for(3);
+        assert_eq!(clock.now(), Duration::from_millis(350));
+
+        assert_eq!(
+            late,
+            LateFrame {
+                frame: 1,
+                took: Duration::from_millis(250),
+                budget: Duration::from_millis(100),
+                dropped: 2,
+            }
+        );
+        assert_eq!(late.to_string(), "frame 1 took 250.0ms, over its 100.0ms budget (2 frames dropped)");
+    }
+}
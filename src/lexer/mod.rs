@@ -9,14 +9,26 @@ use strum_macros::Display;
 use anyhow::Result;
 use thiserror::Error;
 
+use crate::diagnostic::{Diagnostic, Diagnostics, Span};
+
 #[derive(Error, Debug)]
 enum LexerError {
-    #[error("Unrecognized char '{0}' at line {1}")]
-    UnrecognizedChar(char, usize),
-    #[error("Could not parse range at line {0}. Expected a digit after ..")]
-    Range(usize),
-    #[error("Unterminated consumption until char '{0:?}' at line {2}. Consumed: {1}")]
-    UnterminatedConsumption(Vec<char>, String, usize),
+    #[error("Unrecognized char '{0}'")]
+    UnrecognizedChar(char),
+    #[error("Integer literal '{0}' does not fit in 64 bits")]
+    IntOverflow(String),
+    #[error("Frame duration '{0}' is too large")]
+    FpsOverflow(String),
+    #[error("Invalid float literal '{0}'")]
+    InvalidFloat(String),
+    #[error("Unterminated consumption until char '{0:?}'. Consumed: {1}")]
+    UnterminatedConsumption(Vec<char>, String),
+    #[error("Unknown escape sequence '\\{0}'")]
+    UnknownEscape(char),
+    #[error("Invalid unicode escape '\\u{0}'")]
+    InvalidUnicodeEscape(String),
+    #[error("Unclosed '{{' in string, write '\\{{' for a literal brace")]
+    UnclosedInterpolation,
 }
 lazy_static! {
     pub static ref KEYWORDS: HashMap<&'static str, TokenType> = {
@@ -24,6 +36,7 @@ lazy_static! {
         HashMap::from([
             ("let", Declaration),
             ("fn", FunctionDeclaration),
+            ("return", Return),
             ("it", It), // Reference to index inside the for loop
             ("if", If),
             ("else", Else),
@@ -31,6 +44,11 @@ lazy_static! {
             ("while", While),
             ("print", Print),
             ("println", Println),
+            ("wait", Wait),
+            ("yield", Yield),
+            ("every", Every),
+            ("at", At),
+            ("as", As),
             ("true", True),
             ("false", False),
             ("null", Null),
@@ -38,11 +56,17 @@ lazy_static! {
     };
 }
 
+/// whether `ch` can continue an identifier, which starts with a letter
+pub fn is_identifier_char(ch: char) -> bool {
+    ch.is_alphanumeric()
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Display)]
 pub enum TokenType {
     // single char
     Fps,    // #
     FpsEnd, // ## End program
+    FpsForever, // #* Last section, repeated until `exit()`
     Comma,
     Semicolon,
     Colon,
@@ -50,6 +74,8 @@ pub enum TokenType {
     CloseParen,
     OpenBrace,
     CloseBrace,
+    OpenBracket,
+    CloseBracket,
     Plus,
     Minus,
     Star,
@@ -79,6 +105,7 @@ pub enum TokenType {
 
     // keywords
     FunctionDeclaration, // fn
+    Return,
     Declaration, // let
     If,
     Else,
@@ -89,6 +116,11 @@ pub enum TokenType {
     It, // Reference to index inside the for loop
     Print,
     Println,
+    Wait,
+    Yield,
+    Every,
+    At,
+    As,
 
     // Ignore
     Comment,
@@ -99,11 +131,22 @@ pub enum TokenType {
 
 #[derive(Debug, Clone, PartialEq, Display)]
 pub enum LiteralValue {
+    Int(i64),
     Float(f64),
     StringValue(String),
     Identifier(String),
     Keyword(String),
     Fps(usize),
+    /// string with `{expr}` interpolations
+    Interpolation(Vec<StringPart>),
+}
+
+/// Piece of an interpolated string
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Text(String),
+    /// tokens of an interpolated expression, ending with an `Eof` at its closing `}`
+    Expr(Vec<Token>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -112,6 +155,9 @@ pub struct Token {
     pub lexeme: String,
     pub literal: Option<LiteralValue>,
     pub line: usize,
+    /// char offset of the first char of the token
+    pub start: usize,
+    /// char offset right after the last char of the token
     pub pos: usize,
     // pub fps: usize,
 }
@@ -134,16 +180,21 @@ impl Display for Token {
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, lexeme: String, literal: Option<LiteralValue>, line: usize, pos: usize) -> Self {
+    pub fn new(token_type: TokenType, lexeme: String, literal: Option<LiteralValue>, line: usize, start: usize, pos: usize) -> Self {
         Self {
             token_type,
             lexeme,
             literal,
             line,
+            start,
             pos,
             // fps,
         }
     }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.start, self.pos)
+    }
 }
 
 pub struct FpsInput<'a> {
@@ -153,21 +204,23 @@ pub struct FpsInput<'a> {
     current: usize,
     line: usize,
     // current_fps: usize,
+    // the input ended inside a string
+    unterminated: bool,
 }
 
 impl Display for FpsInput<'_> {
     fn fmt(&self, format: &mut Formatter<'_>) -> fmt::Result {
-        write!(format, "Lexer:\n")?;
+        writeln!(format, "Lexer:")?;
         for v in &self.tokens {
-            write!(format, "\t{}\n", v)?;
+            writeln!(format, "\t{}", v)?;
         }
         Ok(())
     }
 }
 
 macro_rules! token {
-    ($token_type: expr, $lexeme: expr, $literal: expr, $line: expr, $pos: expr) => {
-        Token::new($token_type, $lexeme, $literal, $line, $pos)
+    ($token_type: expr, $lexeme: expr, $literal: expr, $line: expr, $start: expr, $pos: expr) => {
+        Token::new($token_type, $lexeme, $literal, $line, $start, $pos)
     };
 }
 
@@ -180,36 +233,53 @@ impl<'a> FpsInput<'a> {
             current: 0,
             line: 1,
             // current_fps: 0,
+            unterminated: false,
         }
     }
 
+    /// whether scanning failed on a string left open at the end of the input, which more input could close
+    pub fn is_unterminated(&self) -> bool {
+        self.unterminated
+    }
+
     fn is_at_end(&mut self) -> bool {
         self.current >= self.input.len()
     }
 
     fn create_token(&self, token_type: TokenType, lexeme: String, literal: Option<LiteralValue>) -> Token {
-        token!(token_type, lexeme, literal, self.line, self.current)
+        token!(token_type, lexeme, literal, self.line, self.start, self.current)
     }
 
     pub fn scan_tokens(&mut self) -> Result<()> {
+        let mut diagnostics: Vec<Diagnostic> = vec![];
+
         while !self.is_at_end() {
             self.start = self.current;
-            if let Some(token) = self.tokenzine()? {
-                match token.token_type {
+            match self.tokenzine() {
+                Ok(Some(token)) => match token.token_type {
                     // ignore whitespaces
                     TokenType::Whitespace => {}
                     TokenType::Eol => {
                         self.line += 1;
                     }
                     _ => self.tokens.push(token),
-                }
-            } else {
-                break;
+                },
+                Ok(None) => break,
+                // keep scanning so every invalid char is reported at once
+                Err(err) => match err.downcast::<Diagnostics>() {
+                    // strings report their own located errors
+                    Ok(located) => diagnostics.extend(located.0),
+                    Err(err) => diagnostics.push(Diagnostic::error(err.to_string(), Span::new(self.start, self.current))),
+                },
             }
         }
 
+        self.start = self.current;
         self.tokens.push(self.create_token(TokenType::Eof, "".to_owned(), None));
 
+        if !diagnostics.is_empty() {
+            return Err(Diagnostics(diagnostics).into());
+        }
         Ok(())
     }
 
@@ -218,65 +288,22 @@ impl<'a> FpsInput<'a> {
         Ok(ch)
     }
 
+    fn peek_next(&mut self) -> Result<Option<char>> {
+        let ch = self.input.chars().nth(self.current + 1);
+        Ok(ch)
+    }
+
     fn advance(&mut self) -> Result<Option<char>> {
         let ch = self.peek();
         self.current += 1;
         ch
     }
 
-    fn consume_until(&mut self, chars: Vec<char>) -> Result<String> {
-        let mut consumed = "".to_string();
-        loop {
-            match self.peek() {
-                Ok(is_next) => {
-                    if let Some(next) = is_next {
-                        if chars.contains(&next) {
-                            break;
-                        } else {
-                            consumed.push_str(next.to_string().as_str());
-                            self.current += 1;
-                        }
-                    } else {
-                        return Err(LexerError::UnterminatedConsumption(chars, consumed, self.line).into());
-                    }
-                }
-                //Eof
-                Err(_) => return Err(LexerError::UnterminatedConsumption(chars, consumed, self.line).into()),
-            }
-        }
-        Ok(consumed)
-    }
-
-    fn next_chars_match(&mut self, chars: Vec<char>) -> Result<bool> {
-        let current = self.current;
-        let mut is_same = false;
-        for ch in chars {
-            if self.is_next_char_match(ch) {
-                is_same = true;
-                self.advance()?;
-            } else {
-                is_same = false;
-                break;
-            }
-        }
-
-        // go back to initial position if chars don't match
-        if !is_same {
-            self.current = current;
-        }
-
-        Ok(is_same)
-    }
-
     fn is_next_char_match(&mut self, ch: char) -> bool {
         match self.peek() {
             Ok(is_next) => {
                 if let Some(next) = is_next {
-                    if next == ch {
-                        true
-                    } else {
-                        false
-                    }
+                    next == ch
                 } else {
                     false
                 }
@@ -286,120 +313,245 @@ impl<'a> FpsInput<'a> {
         }
     }
 
+    /// consume the rest of the line, the last line of the input doesn't need a line break
     fn consume_until_eol(&mut self) -> Result<String> {
-        self.consume_until(vec!['\n', '\r'])
+        let consumed: String = self.input.chars().skip(self.current).take_while(|ch| *ch != '\n' && *ch != '\r').collect();
+        self.current += consumed.chars().count();
+        Ok(consumed)
     }
 
     fn consume_fps(&mut self) -> Result<String> {
         let mut consumed = "#".to_owned();
 
+        while let Ok(Some(next)) = self.peek() {
+            if next.is_ascii_digit() {
+                consumed.push_str(next.to_string().as_str());
+                self.current += 1;
+            } else {
+                break;
+            }
+        }
+
+        Ok(consumed)
+    }
+
+    /// consume a string after its opening quote, resolving escapes and lexing its `{expr}` interpolations
+    ///
+    /// An empty `{}` is kept as text
+    fn consume_string(&mut self) -> Result<(String, LiteralValue)> {
+        let mut parts: Vec<StringPart> = vec![];
+        let mut text = "".to_owned();
+        let mut errors: Vec<Diagnostic> = vec![];
+
         loop {
-            match self.peek() {
-                Ok(is_next) => {
-                    if let Some(next) = is_next {
-                        if next.is_digit(10) {
-                            consumed.push_str(next.to_string().as_str());
-                            self.current += 1;
-                        } else {
-                            break;
+            let start = self.current;
+            match self.peek()? {
+                None => {
+                    let consumed = self.input.chars().skip(self.start + 1).collect();
+                    self.unterminated = true;
+                    return Err(LexerError::UnterminatedConsumption(vec!['"'], consumed).into());
+                }
+                Some('"') => {
+                    self.current += 1;
+                    break;
+                }
+                Some('\\') => {
+                    self.current += 1;
+                    match self.consume_escape() {
+                        Ok(ch) => text.push(ch),
+                        Err(err) => errors.push(Diagnostic::error(err.to_string(), Span::new(start, self.current))),
+                    }
+                }
+                Some('{') if !matches!(self.peek_next()?, Some('}')) => {
+                    self.current += 1;
+                    let source = self.consume_interpolation(start)?;
+                    match self.scan_interpolation(&source, start + 1) {
+                        Ok(tokens) => {
+                            if !text.is_empty() {
+                                parts.push(StringPart::Text(std::mem::take(&mut text)));
+                            }
+                            parts.push(StringPart::Expr(tokens));
                         }
+                        Err(err) => match err.downcast::<Diagnostics>() {
+                            Ok(located) => errors.extend(located.0),
+                            Err(err) => errors.push(Diagnostic::error(err.to_string(), Span::new(start, self.current))),
+                        },
                     }
                 }
-                Err(_) => (),
+                Some(ch) => {
+                    self.current += 1;
+                    text.push(ch);
+                }
             }
         }
 
-        Ok(consumed)
+        if !errors.is_empty() {
+            return Err(Diagnostics(errors).into());
+        }
+
+        let lexeme = self.input.chars().skip(self.start + 1).take(self.current - self.start - 2).collect();
+        if parts.is_empty() {
+            return Ok((lexeme, LiteralValue::StringValue(text)));
+        }
+        if !text.is_empty() {
+            parts.push(StringPart::Text(text));
+        }
+        Ok((lexeme, LiteralValue::Interpolation(parts)))
     }
 
-    fn consume_string(&mut self) -> Result<String> {
-        let consumed = self.consume_until(vec!['"']);
+    /// char escaped by the `\` just consumed
+    fn consume_escape(&mut self) -> Result<char> {
+        let ch = match self.peek()? {
+            Some(ch) => ch,
+            // the unterminated string is reported by `consume_string`
+            None => return Ok('\\'),
+        };
         self.current += 1;
-        consumed
+
+        match ch {
+            '"' => Ok('"'),
+            '\\' => Ok('\\'),
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            '{' => Ok('{'),
+            '}' => Ok('}'),
+            'u' => self.consume_unicode_escape(),
+            _ => Err(LexerError::UnknownEscape(ch).into()),
+        }
     }
 
-    fn consume_number(&mut self) -> String {
+    /// `{XXXX}` part of a `\u{XXXX}` escape, up to 6 hex digits
+    fn consume_unicode_escape(&mut self) -> Result<char> {
         let mut consumed = "".to_owned();
-        loop {
-            match self.peek() {
-                Ok(is_next) => {
-                    if let Some(next) = is_next {
-                        if next.is_digit(10) {
-                            consumed.push_str(next.to_string().as_str());
-                            self.current += 1;
-                        } else if self.is_next_char_match('.') {
-                            // check if consumed already has more dots??
-                            if consumed.contains(".") {
-                                break;
-                            }
-
-                            consumed.push_str(next.to_string().as_str());
-                            self.current += 1;
-                            consumed += self.consume_number().as_str();
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                //Eof
-                Err(_) => break,
+        if !self.is_next_char_match('{') {
+            return Err(LexerError::InvalidUnicodeEscape(consumed).into());
+        }
+        self.current += 1;
+        consumed.push('{');
+
+        while let Some(next) = self.peek()? {
+            if next == '}' {
+                self.current += 1;
+                consumed.push(next);
+                let code = u32::from_str_radix(&consumed[1..consumed.len() - 1], 16).ok();
+                return match code.and_then(char::from_u32) {
+                    Some(ch) if consumed.len() <= 8 => Ok(ch),
+                    _ => Err(LexerError::InvalidUnicodeEscape(consumed).into()),
+                };
+            }
+            // leave the closing quote to `consume_string`
+            if !next.is_ascii_hexdigit() {
+                break;
             }
+            self.current += 1;
+            consumed.push(next);
         }
 
-        consumed
+        Err(LexerError::InvalidUnicodeEscape(consumed).into())
     }
 
-    fn consume_identifier(&mut self) -> String {
+    /// source of an interpolation after its opening `{` at `open`, until the matching `}` which is consumed
+    fn consume_interpolation(&mut self, open: usize) -> Result<String> {
         let mut consumed = "".to_owned();
-        loop {
-            match self.peek() {
-                Ok(is_next) => {
-                    if let Some(next) = is_next {
-                        if next.is_alphanumeric() {
-                            consumed.push_str(next.to_string().as_str());
-                            self.current += 1;
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
+        let mut depth = 0;
+        let mut in_string = false;
+
+        while let Some(next) = self.advance()? {
+            match next {
+                '\\' if in_string => {
+                    consumed.push(next);
+                    if let Some(escaped) = self.advance()? {
+                        consumed.push(escaped);
                     }
+                    continue;
                 }
-                //Eof
-                Err(_) => break,
+                '"' => in_string = !in_string,
+                '{' if !in_string => depth += 1,
+                '}' if !in_string && depth == 0 => return Ok(consumed),
+                '}' if !in_string => depth -= 1,
+                _ => {}
             }
+            consumed.push(next);
         }
 
-        consumed
+        // the brace swallowed the rest of the input, closing quote included
+        self.unterminated = true;
+        let diagnostic = Diagnostic::error(LexerError::UnclosedInterpolation.to_string(), Span::new(open, open + 1));
+        Err(Diagnostics(vec![diagnostic]).into())
     }
 
-    fn consume_range(&mut self) -> Result<String> {
-        let mut consumed = "..".to_owned();
+    /// tokens of an interpolated expression, located in the whole input
+    fn scan_interpolation(&self, source: &str, offset: usize) -> Result<Vec<Token>> {
+        let mut scanner = FpsInput::new(source);
+        let result = scanner.scan_tokens();
 
-        if self.is_next_char_match('=') {
-            consumed.push(self.advance().unwrap().unwrap());
+        if let Err(err) = result {
+            return match err.downcast::<Diagnostics>() {
+                Ok(Diagnostics(diagnostics)) => Err(Diagnostics(
+                    diagnostics
+                        .into_iter()
+                        .map(|mut diagnostic| {
+                            diagnostic.span = Span::new(diagnostic.span.start + offset, diagnostic.span.end + offset);
+                            diagnostic
+                        })
+                        .collect(),
+                )
+                .into()),
+                Err(err) => Err(err),
+            };
         }
 
-        match self.peek() {
-            Ok(is_next) => {
-                if let Some(next) = is_next {
-                    if next.is_digit(10) {
-                        consumed.push_str(next.to_string().as_str());
-                        self.current += 1;
-                    } else {
-                        return Err(LexerError::Range(self.line).into());
-                    }
-                } else {
-                    return Err(LexerError::Range(self.line).into());
+        Ok(scanner
+            .tokens
+            .into_iter()
+            .map(|mut token| {
+                token.start += offset;
+                token.pos += offset;
+                token.line += self.line - 1;
+                token
+            })
+            .collect())
+    }
+
+    fn consume_number(&mut self) -> String {
+        let mut consumed = "".to_owned();
+        while let Ok(Some(next)) = self.peek() {
+            if next.is_ascii_digit() {
+                consumed.push_str(next.to_string().as_str());
+                self.current += 1;
+            } else if self.is_next_char_match('.') {
+                // check if consumed already has more dots??
+                if consumed.contains('.') {
+                    break;
+                }
+                // `..` is the range operator, not a decimal point
+                if let Ok(Some('.')) = self.peek_next() {
+                    break;
                 }
+
+                consumed.push_str(next.to_string().as_str());
+                self.current += 1;
+                consumed += self.consume_number().as_str();
+            } else {
+                break;
             }
-            //Eof
-            Err(_) => return Err(LexerError::Range(self.line).into()),
         }
 
-        Ok(consumed)
+        consumed
+    }
+
+    fn consume_identifier(&mut self) -> String {
+        let mut consumed = "".to_owned();
+        while let Ok(Some(next)) = self.peek() {
+            if is_identifier_char(next) {
+                consumed.push_str(next.to_string().as_str());
+                self.current += 1;
+            } else {
+                break;
+            }
+        }
+
+        consumed
     }
 
     fn tokenzine(&mut self) -> Result<Option<Token>> {
@@ -418,7 +570,7 @@ impl<'a> FpsInput<'a> {
                     if self.is_next_char_match('/') {
                         self.current += 1;
                         let comment = self.consume_until_eol()?;
-                        self.create_token(Comment, comment.into(), None)
+                        self.create_token(Comment, comment, None)
                     } else {
                         self.create_token(Slash, ch.into(), None)
                     }
@@ -431,17 +583,36 @@ impl<'a> FpsInput<'a> {
                 ')' => self.create_token(CloseParen, ch.into(), None),
                 '{' => self.create_token(OpenBrace, ch.into(), None),
                 '}' => self.create_token(CloseBrace, ch.into(), None),
+                '[' => self.create_token(OpenBracket, ch.into(), None),
+                ']' => self.create_token(CloseBracket, ch.into(), None),
+                '.' if self.is_next_char_match('.') => {
+                    self.current += 1;
+                    if self.is_next_char_match('=') {
+                        self.current += 1;
+                        self.create_token(RangeEqual, "..=".to_owned(), None)
+                    } else {
+                        self.create_token(Range, "..".to_owned(), None)
+                    }
+                }
                 // single or double char
                 '#' => {
                     // self.current_fps += 1;
                     if self.is_next_char_match('#') {
                         self.current += 1;
                         self.create_token(FpsEnd, "##".to_owned(), None)
+                    } else if self.is_next_char_match('*') {
+                        self.current += 1;
+                        self.create_token(FpsForever, "#*".to_owned(), None)
                     } else {
                         let fps = self.consume_fps()?;
-                        if fps.len() > 1 {
-                            let fps_count = fps.replace("#", "").parse::<usize>().unwrap();
-                            self.create_token(Fps, format!("#{}", fps_count), Some(LiteralValue::Fps(fps_count)))
+                        if fps.len() == 1 && self.is_next_char_match('(') {
+                            // `#(expr)` duration is only known at runtime
+                            self.create_token(Fps, fps, None)
+                        } else if fps.len() > 1 {
+                            match fps.replace("#", "").parse::<usize>() {
+                                Ok(fps_count) => self.create_token(Fps, format!("#{}", fps_count), Some(LiteralValue::Fps(fps_count))),
+                                Err(_) => return Err(LexerError::FpsOverflow(fps).into()),
+                            }
                         } else {
                             self.create_token(Fps, ch.into(), Some(LiteralValue::Fps(1)))
                         }
@@ -479,6 +650,8 @@ impl<'a> FpsInput<'a> {
                         self.create_token(Less, ch.into(), None)
                     }
                 }
+                // spelled out like the other two-character operators, a lone `&` or `|` falls to `match_default`
+                #[allow(clippy::collapsible_match)]
                 '&' => {
                     if self.is_next_char_match('&') {
                         self.current += 1;
@@ -487,6 +660,7 @@ impl<'a> FpsInput<'a> {
                         self.match_default(ch)?
                     }
                 }
+                #[allow(clippy::collapsible_match)]
                 '|' => {
                     if self.is_next_char_match('|') {
                         self.current += 1;
@@ -497,12 +671,8 @@ impl<'a> FpsInput<'a> {
                 }
                 // literals
                 '"' => {
-                    let string_literal = self.consume_string()?;
-                    self.create_token(
-                        StringLiteral,
-                        string_literal.clone(),
-                        Some(LiteralValue::StringValue(string_literal)),
-                    )
+                    let (lexeme, literal) = self.consume_string()?;
+                    self.create_token(StringLiteral, lexeme, Some(literal))
                 }
 
                 _ => {
@@ -517,19 +687,20 @@ impl<'a> FpsInput<'a> {
 
     fn match_default(&mut self, ch: char) -> Result<Token> {
         use TokenType::*;
-        let token = if ch.is_digit(10) {
+        let token = if ch.is_ascii_digit() {
             let mut num: String = ch.into();
-            if self.next_chars_match(vec!['.', '.'])? {
-                let mut range = num;
-                range.push_str(self.consume_range()?.as_str());
-                if range.contains("=") {
-                    self.create_token(RangeEqual, range.clone(), None)
-                } else {
-                    self.create_token(Range, range.clone(), None)
+            num.push_str(self.consume_number().as_str());
+            // `1` is an int, `1.` and `1.0` are floats
+            if num.contains('.') {
+                match num.parse::<f64>() {
+                    Ok(float) => self.create_token(Number, num.clone(), Some(LiteralValue::Float(float))),
+                    Err(_) => return Err(LexerError::InvalidFloat(num).into()),
                 }
             } else {
-                num.push_str(self.consume_number().as_str());
-                self.create_token(Number, num.clone(), Some(LiteralValue::Float(num.parse::<f64>().unwrap())))
+                match num.parse::<i64>() {
+                    Ok(int) => self.create_token(Number, num.clone(), Some(LiteralValue::Int(int))),
+                    Err(_) => return Err(LexerError::IntOverflow(num).into()),
+                }
             }
         } else if ch.is_alphabetic() {
             let mut id: String = ch.into();
@@ -541,11 +712,10 @@ impl<'a> FpsInput<'a> {
                 self.create_token(Identifer, id.clone(), Some(LiteralValue::Identifier(id)))
             }
         } else {
-            self.current += 1;
-            return Err(LexerError::UnrecognizedChar(ch, self.line).into());
+            return Err(LexerError::UnrecognizedChar(ch).into());
         };
 
-        return Ok(token);
+        Ok(token)
     }
 }
 
@@ -573,13 +743,13 @@ mod tests {
 
     #[test]
     fn two_char_tokens() {
-        let input = "#10 == != >= <= && || ";
-        let expected = vec![Fps, EqualEqual, BangEqual, GreaterEqual, LessEqual, And, Or, Eof];
+        let input = "#10 #* == != >= <= && || ";
+        let expected = vec![Fps, FpsForever, EqualEqual, BangEqual, GreaterEqual, LessEqual, And, Or, Eof];
 
         let mut scanner = FpsInput::new(input);
         let _tokens = scanner.scan_tokens();
 
-        assert_eq!(scanner.tokens.len(), 8); //Eof counts as a Token
+        assert_eq!(scanner.tokens.len(), 9); //Eof counts as a Token
         assert_eq!(
             scanner.tokens.into_iter().map(|x| x.token_type).collect::<Vec<TokenType>>(),
             expected
@@ -588,19 +758,33 @@ mod tests {
 
     #[test]
     fn types() {
-        let input = "0..1 1..=2";
-        let expected = vec![Range, RangeEqual, Eof];
+        let input = "0..1 1..=n 10..-2";
+        let expected = vec![Number, Range, Number, Number, RangeEqual, Identifer, Number, Range, Minus, Number, Eof];
 
         let mut scanner = FpsInput::new(input);
         let _tokens = scanner.scan_tokens();
 
-        assert_eq!(scanner.tokens.len(), 3); //Eof counts as a Token
+        assert_eq!(scanner.tokens.len(), 11); //Eof counts as a Token
         assert_eq!(
             scanner.tokens.into_iter().map(|x| x.token_type).collect::<Vec<TokenType>>(),
             expected
         );
     }
 
+    #[test]
+    fn comment_at_end_of_input() {
+        let input = "a; //last";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        assert_eq!(scanner.tokens[2].lexeme, "last");
+        assert_eq!(
+            scanner.tokens.into_iter().map(|x| x.token_type).collect::<Vec<TokenType>>(),
+            vec![Identifer, Semicolon, Comment, Eof]
+        );
+    }
+
     #[test]
     fn comment() {
         let input = "//I am a comment\n";
@@ -636,6 +820,60 @@ mod tests {
         );
     }
 
+    #[test]
+    fn int_and_float_literals() {
+        let input = "1 1. 1.0";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        assert_eq!(
+            scanner.tokens.into_iter().map(|x| x.literal).collect::<Vec<Option<LiteralValue>>>(),
+            vec![Some(LiteralValue::Int(1)), Some(LiteralValue::Float(1.)), Some(LiteralValue::Float(1.)), None]
+        );
+    }
+
+    #[test]
+    fn invalid_number_literals() {
+        let input = "print(1.2.3); 99999999999999999999";
+
+        let mut scanner = FpsInput::new(input);
+        let result = scanner.scan_tokens();
+
+        let diagnostics = result.unwrap_err().downcast::<Diagnostics>().unwrap();
+        assert_eq!(
+            diagnostics.to_string(),
+            "Invalid float literal '1.2.3'\nInteger literal '99999999999999999999' does not fit in 64 bits"
+        );
+        assert_eq!(diagnostics.0[0].span, Span::new(6, 11));
+    }
+
+    #[test]
+    fn fps_overflow() {
+        let input = "#99999999999999999999999 ##";
+
+        let mut scanner = FpsInput::new(input);
+        let result = scanner.scan_tokens();
+
+        let diagnostics = result.unwrap_err().downcast::<Diagnostics>().unwrap();
+        assert_eq!(diagnostics.to_string(), "Frame duration '#99999999999999999999999' is too large");
+        assert_eq!(diagnostics.0[0].span, Span::new(0, 24));
+        assert_eq!(scanner.tokens.into_iter().map(|x| x.token_type).collect::<Vec<TokenType>>(), vec![FpsEnd, Eof]);
+    }
+
+    #[test]
+    fn brackets() {
+        let input = "s[0..2]";
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        assert_eq!(
+            scanner.tokens.into_iter().map(|x| x.token_type).collect::<Vec<TokenType>>(),
+            vec![Identifer, OpenBracket, Number, Range, Number, CloseBracket, Eof]
+        );
+    }
+
     #[test]
     fn numeric_literal() {
         let input = "123.123";
@@ -679,19 +917,99 @@ mod tests {
 
         assert_eq!(
             format!("{}", result.unwrap_err().root_cause()),
-            "Unterminated consumption until char '['\"']' at line 1. Consumed: I do not end..."
+            "Unterminated consumption until char '['\"']'. Consumed: I do not end..."
+        );
+        assert!(scanner.is_unterminated());
+    }
+
+    #[test]
+    fn string_escapes() {
+        let input = r#""a\"b\\c\nd\te\u{48}\{}""#;
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        assert_eq!(scanner.tokens[0].literal, Some(LiteralValue::StringValue("a\"b\\c\nd\teH{}".to_owned())));
+        assert_eq!(scanner.tokens[0].lexeme, r#"a\"b\\c\nd\te\u{48}\{}"#);
+    }
+
+    #[test]
+    fn invalid_escapes() {
+        let input = r#""\q \u{110000} \u48" 1"#;
+
+        let mut scanner = FpsInput::new(input);
+        let result = scanner.scan_tokens();
+
+        let diagnostics = result.unwrap_err().downcast::<Diagnostics>().unwrap();
+        assert_eq!(
+            diagnostics.to_string(),
+            "Unknown escape sequence '\\q'\nInvalid unicode escape '\\u{110000}'\nInvalid unicode escape '\\u'"
         );
+        assert_eq!(diagnostics.0[1].span, Span::new(4, 14));
+        // scanning resumes after the string
+        assert_eq!(scanner.tokens.into_iter().map(|x| x.token_type).collect::<Vec<TokenType>>(), vec![Number, Eof]);
+    }
+
+    #[test]
+    fn string_interpolation() {
+        let input = r#""a {b + "}"} {}""#;
+
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().unwrap();
+
+        let parts = match &scanner.tokens[0].literal {
+            Some(LiteralValue::Interpolation(parts)) => parts.clone(),
+            literal => panic!("expected an interpolation, got {:?}", literal),
+        };
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], StringPart::Text("a ".to_owned()));
+        assert_eq!(parts[2], StringPart::Text(" {}".to_owned()));
+
+        let StringPart::Expr(tokens) = &parts[1] else {
+            panic!("expected an interpolated expression")
+        };
+        assert_eq!(
+            tokens.iter().map(|x| x.token_type).collect::<Vec<TokenType>>(),
+            vec![Identifer, Plus, StringLiteral, Eof]
+        );
+        // tokens are located in the whole input
+        assert_eq!(tokens[0].span(), Span::new(4, 5));
+    }
+
+    #[test]
+    fn unclosed_interpolation() {
+        let input = r#"print("a {"); ##"#;
+
+        let mut scanner = FpsInput::new(input);
+        let result = scanner.scan_tokens();
+
+        let diagnostics = result.unwrap_err().downcast::<Diagnostics>().unwrap();
+        assert_eq!(diagnostics.to_string(), "Unclosed '{' in string, write '\\{' for a literal brace");
+        assert_eq!(diagnostics.0[0].span, Span::new(9, 10));
+        assert!(scanner.is_unterminated());
+    }
+
+    #[test]
+    fn unrecognized_chars() {
+        let input = "@ 1 $";
+
+        let mut scanner = FpsInput::new(input);
+        let result = scanner.scan_tokens();
+
+        let diagnostics = result.unwrap_err().downcast::<Diagnostics>().unwrap();
+        assert_eq!(diagnostics.to_string(), "Unrecognized char '@'\nUnrecognized char '$'");
+        assert_eq!(diagnostics.0[1].span, Span::new(4, 5));
     }
 
     #[test]
     fn keywords() {
-        let input = "for forca print println";
-        let expected = vec![For, Identifer, Print, Println, Eof];
+        let input = "for forca print println wait yield every at as";
+        let expected = vec![For, Identifer, Print, Println, Wait, Yield, Every, At, As, Eof];
 
         let mut scanner = FpsInput::new(input);
         let _ = scanner.scan_tokens();
 
-        assert_eq!(scanner.tokens.len(), 5); //Eof counts as a Token
+        assert_eq!(scanner.tokens.len(), 10); //Eof counts as a Token
         assert_eq!(scanner.tokens[0].literal, Some(LiteralValue::Keyword("for".to_owned())));
         assert_eq!(scanner.tokens[2].literal, Some(LiteralValue::Keyword("print".to_owned())));
         assert_eq!(scanner.tokens[3].literal, Some(LiteralValue::Keyword("println".to_owned())));
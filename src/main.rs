@@ -1,111 +1,207 @@
-use std::str;
 use anyhow::Result;
-use clap::Parser as ClapParser;
-use std::io::{stdin, stdout, Write};
-
-use crate::interpreter::Interpreter;
+use clap::{Parser as ClapParser, ValueEnum};
+use std::fs;
+use std::io::{stdin, stdout, Read};
+use std::process::ExitCode;
+use std::thread;
+use thiserror::Error;
+
+use crate::diagnostic::{render_error, render_input_error};
+use crate::interpreter::debugger::Debugger;
+use crate::interpreter::output::{JsonLinesSink, Sink, TextSink, DEFAULT_PREFIX};
+use crate::interpreter::realtime::SystemClock;
+use crate::ast::statement::Statement;
+use crate::interpreter::{Interpreter, DEFAULT_BUDGET, STACK_SIZE};
 use crate::lexer::FpsInput;
 use crate::parser::Parser;
+use crate::repl::Repl;
 
 mod ast;
+mod diagnostic;
 mod interpreter;
 mod lexer;
 mod parser;
+mod repl;
+
+/// exit codes follow the BSD `sysexits.h` convention
+const EXIT_DATA_ERR: u8 = 65;
+const EXIT_NO_INPUT: u8 = 66;
+const EXIT_SOFTWARE: u8 = 70;
 
 #[derive(ClapParser, Debug)]
 #[command(author, version, about)]
 struct Cli {
+    /// path to the .fps file to execute ('-' reads the program from stdin)
+    #[arg(required_unless_present = "repl")]
+    path: Option<String>,
     /// flag to access the REPL
     #[arg(short, long)]
     repl: bool,
+    /// format of the printed values
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Prefixed)]
+    format: OutputFormat,
+    /// prefix of every output line of the prefixed format, `{frame}` is replaced by the frame number
+    #[arg(long, default_value = DEFAULT_PREFIX)]
+    prefix: String,
+    /// play the frames in real time at the given frame rate, instead of as fast as possible
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    fps: Option<u32>,
+    /// stop with an error once the program runs past this frame
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_frames: Option<u64>,
+    /// statements and loop iterations a frame can run before it is aborted
+    #[arg(long, default_value_t = DEFAULT_BUDGET as u64, value_parser = clap::value_parser!(u64).range(1..))]
+    budget: u64,
+    /// pause before every frame to step through the program, commands are read from stdin
+    #[arg(long, conflicts_with_all = ["repl", "fps"])]
+    debug: bool,
 }
 
-fn execute(interpreter: &mut Interpreter, input: &str) -> Result<()> {
-    let mut stdout = Vec::new();
-    
-    let mut scanner = FpsInput::new(input);
-    scanner.scan_tokens()?;
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    /// lines starting with the prefix
+    Prefixed,
+    /// printed values only
+    Plain,
+    /// a `{frame, line, value, type}` JSON record per print
+    Json,
+}
 
-    // println!("{}", scanner);
+impl Cli {
+    fn interpreter(&self) -> Interpreter {
+        let sink: Box<dyn Sink> = match self.format {
+            OutputFormat::Prefixed => Box::new(TextSink::prefixed(&self.prefix)),
+            OutputFormat::Plain => Box::new(TextSink::plain()),
+            OutputFormat::Json => Box::new(JsonLinesSink),
+        };
+        let mut interpreter = Interpreter::new().with_sink(sink).with_budget(self.budget as usize);
+        if let Some(max_frames) = self.max_frames {
+            interpreter = interpreter.with_max_frames(max_frames as usize);
+        }
+        match self.fps {
+            Some(fps) => interpreter
+                .with_fps(fps, Box::new(SystemClock::new()))
+                .with_late_frame_report(|late| eprintln!("warning: {}", late)),
+            None => interpreter,
+        }
+    }
+}
 
-    let mut parser = Parser::new(scanner.tokens);
+#[derive(Error, Debug)]
+enum FpsError {
+    /// diagnostics rendered against the source
+    #[error("{0}")]
+    Compile(String),
+    /// runtime error rendered with its frame and stack
+    #[error("{0}")]
+    Runtime(String),
+}
 
-    let res = parser.parse();
-    match res {
-        Ok(statements) => {
-            // println!("statements: {:?}", statements);
-            interpreter.interpret(&mut stdout, statements)?;
+impl FpsError {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            FpsError::Compile(_) => ExitCode::from(EXIT_DATA_ERR),
+            FpsError::Runtime(_) => ExitCode::from(EXIT_SOFTWARE),
         }
-        Err(res) => println!("ERROR: {:?}", res),
     }
+}
 
-    match str::from_utf8(&stdout) {
-        Ok(val) => println!("{}", val),
-        Err(err) => panic!("{}", err.to_string())
-    }
-    
+/// how the source handed to `execute` relates to what already ran
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    /// a whole program ending with `##`
+    Program,
+    /// an input of a REPL session, continuing the previous ones
+    Session,
+}
 
-    
+fn compile(input: &str, name: &str, mode: Mode) -> Result<Vec<Statement>, FpsError> {
+    let compile_error = |err: anyhow::Error| FpsError::Compile(render_error(&err, input, name));
 
-    // for statement in statements {
+    let mut scanner = FpsInput::new(input);
+    scanner.scan_tokens().map_err(compile_error)?;
 
-    //     // match statement {
-    //     //     ast::statement::Statement::ArithmeticExpr(expr) => {
-    //     //         interpreter.interpret_expr(expr)?;
-    //     //     }
-    //     //     // ast::statement::Statement::Print(_) => todo!(),
-    //     //     // ast::statement::Statement::Assign { id, expr } => todo!(),
-    //     //     _ => todo!(),
-    //     // }
+    let mut parser = Parser::new(scanner.tokens);
+    match mode {
+        Mode::Program => parser.parse(),
+        Mode::Session => parser.parse_incremental(),
+    }
+    .map_err(compile_error)
+}
 
-    //     // let result =
-    // }
+fn execute(interpreter: &mut Interpreter, input: &str, name: &str, mode: Mode) -> Result<(), FpsError> {
+    let statements = compile(input, name, mode)?;
 
-    Ok(())
+    let mut stdout = stdout().lock();
+    match mode {
+        Mode::Program => interpreter.interpret(&mut stdout, statements),
+        Mode::Session => interpreter.interpret_input(&mut stdout, statements),
+    }
+    .map_err(|err| FpsError::Runtime(render_input_error(&err, interpreter.program(), input, name)))
 }
 
-fn run_prompt() -> Result<()> {
-    println!("# REPL  -  FPS Lang #");
-    println!("--------------------");
-    println!("Type '\\q' to exit");
-    let mut buffer = String::new();
-    let mut interpreter: Interpreter = Interpreter::new();
-    loop {
-        buffer.clear();
-        print!("fps> ");
-        stdout().flush()?;
-        stdin().read_line(&mut buffer)?;
-        // remove LF
-        buffer = buffer.as_str().trim_end().to_string();
-
-        if buffer == "\\q" {
-            break;
-        }
-
-        execute(&mut interpreter, &buffer)?;
+fn read_source(path: &str) -> Result<String> {
+    if path == "-" {
+        let mut input = String::new();
+        stdin().read_to_string(&mut input)?;
+        Ok(input)
+    } else {
+        Ok(fs::read_to_string(path)?)
     }
-    Ok(())
 }
 
-fn run_file(input: &str) -> Result<()> {
-    let mut interpreter: Interpreter = Interpreter::new();
-    execute(&mut interpreter, input)?;
+fn run_file(path: &str, args: &Cli) -> ExitCode {
+    let name = if path == "-" { "<stdin>" } else { path };
 
-    // todo!("fix line POS and multiline processing");
+    let input = match read_source(path) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("ERROR: could not read '{}': {}", name, err);
+            return ExitCode::from(EXIT_NO_INPUT);
+        }
+    };
 
-    Ok(())
+    let mut interpreter: Interpreter = args.interpreter();
+    if args.debug {
+        let debugger = Debugger::new(&input, Box::new(stdin().lock()), Box::new(stdout()));
+        interpreter = interpreter.with_debugger(debugger);
+    }
+    match execute(&mut interpreter, &input, name, Mode::Program) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            // errors are rendered against the file they come from
+            eprintln!("{}", err);
+            err.exit_code()
+        }
+    }
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let args = Cli::parse();
 
-    if !args.repl {
-        // todo!();
-        let input = include_str!("sample.fps");
-        run_file(input)?;
-    } else {
-        run_prompt()?
+    // nested function calls recurse through the interpreter, which needs more than the main thread stack
+    let runner = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || run(&args));
+    match runner {
+        // a panic was already reported by the thread
+        Ok(runner) => runner.join().unwrap_or(ExitCode::from(EXIT_SOFTWARE)),
+        Err(err) => {
+            eprintln!("ERROR: could not start the interpreter: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &Cli) -> ExitCode {
+    if args.repl {
+        if let Err(err) = Repl::new(args).and_then(|mut repl| repl.run()) {
+            eprintln!("ERROR: {}", err);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
     }
 
-    Ok(())
+    match &args.path {
+        Some(path) => run_file(path, args),
+        None => unreachable!("clap requires a path unless the REPL is requested"),
+    }
 }
@@ -1,6 +1,7 @@
 use crate::{
     ast::{expr::Expr, literal::LiteralValue, statement::Statement},
-    lexer::{Token, TokenType, KEYWORDS},
+    diagnostic::{Diagnostic, Diagnostics},
+    lexer::{self, StringPart, Token, TokenType, KEYWORDS},
 };
 
 use anyhow::Result;
@@ -8,20 +9,28 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 enum ParserError {
-    #[error("Could not consume: '{0:?}'")]
+    #[error("{0}")]
     Consume(String),
-    #[error("Expected expression for token: '{0}' at line {1}")]
-    ExpectedExpression(String, usize),
+    #[error("Expected expression")]
+    ExpectedExpression,
     #[error("Expected FPS End token '##' at the end of the file")]
     ExpectedFpsEnd,
-    #[error("Invalid variable declaration: '{0}'")]
+    #[error("The open-ended section '#*' must be the last one")]
+    SectionAfterForever,
+    #[error("Invalid variable declaration: {0}")]
     Declaration(String),
     #[error("Invalid assignment target")]
     InvalidAssignment,
-    #[error("Errors parsing: {0:?}")]
-    MultipleErrors(Vec<String>),
-    #[error("Cannot have more than 255 arguments: line {0}")]
-    MaximumArgumentNumber(usize),
+    #[error("Cannot have more than 255 arguments")]
+    MaximumArgumentNumber,
+    #[error("Duplicate parameter '{0}'")]
+    DuplicateParameter(String),
+    #[error("Cannot return from top-level code")]
+    ReturnOutsideFunction,
+    #[error("'it' can only be used inside a for loop")]
+    ItOutsideFor,
+    #[error("Expected the end of the expression")]
+    ExpectedEndOfExpression,
 }
 
 #[derive(Debug)]
@@ -29,34 +38,76 @@ pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
     // current_fps: usize
+    function_depth: usize,
+    for_depth: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens,
+            current: 0,
+            function_depth: 0,
+            for_depth: 0,
+        }
     }
 
     pub fn parse(&mut self) -> Result<Vec<Statement>> {
+        self.parse_program(true)
+    }
+
+    /// parse an input of a REPL session, which doesn't have to end with `##`
+    pub fn parse_incremental(&mut self) -> Result<Vec<Statement>> {
+        self.parse_program(false)
+    }
+
+    /// parse a lone expression, e.g. one inspected by the debugger
+    ///
+    /// `it` is accepted anywhere, it fails when evaluated outside a `for` loop
+    pub fn parse_expression(&mut self) -> Result<Expr> {
+        self.for_depth += 1;
+        let expr = self.expression().map_err(|err| Diagnostics(vec![self.diagnostic(err)]))?;
+        self.for_depth -= 1;
+
+        if !self.is_at_end() {
+            let token = self.peek();
+            return Err(Diagnostics(vec![self.error_at(&token, ParserError::ExpectedEndOfExpression)]).into());
+        }
+        Ok(expr)
+    }
+
+    fn parse_program(&mut self, require_end: bool) -> Result<Vec<Statement>> {
         let mut statements: Vec<Statement> = vec![];
-        let mut errors: Vec<String> = vec![];
+        let mut errors: Vec<Diagnostic> = vec![];
 
         while !self.is_at_end() {
             let statement = self.declaration();
             match statement {
                 Ok(s) => statements.push(s),
                 Err(err) => {
-                    errors.push(err.to_string());
+                    errors.push(self.diagnostic(err));
                     self.synchronize();
                 }
             }
         }
 
-        if self.previous().token_type != TokenType::FpsEnd {
-            return Err(ParserError::ExpectedFpsEnd.into());
+        // a program ends with `##`, or repeats its open-ended `#*` section until `exit()`
+        let forever = statements.iter().position(|statement| matches!(statement, Statement::FpsForever(_)));
+        if let Some(position) = forever {
+            let marker = statements[position + 1..].iter().find_map(|statement| match statement {
+                Statement::Fps { token, .. } | Statement::FpsForever(token) => Some(token.clone()),
+                _ => None,
+            });
+            if let Some(token) = marker {
+                errors.push(self.error_at(&token, ParserError::SectionAfterForever));
+            }
+        } else if require_end && (self.current == 0 || self.previous().token_type != TokenType::FpsEnd) {
+            let token = self.peek();
+            errors.push(self.error_at(&token, ParserError::ExpectedFpsEnd).expected("'##'".to_owned()));
         }
 
-        if errors.len() > 0 {
-            return Err(ParserError::MultipleErrors(errors).into());
+        if !errors.is_empty() {
+            return Err(Diagnostics(errors).into());
         }
         Ok(statements)
 
@@ -83,10 +134,14 @@ impl Parser {
             match self.declaration_statement() {
                 Ok(s) => Ok(s),
                 Err(err) => {
-                    self.synchronize();
-                    return Err(ParserError::Declaration(err.to_string()).into());
+                    // `parse` synchronizes once the error is reported
+                    let mut diagnostic = self.diagnostic(err);
+                    diagnostic.message = ParserError::Declaration(diagnostic.message).to_string();
+                    Err(diagnostic.into())
                 }
             }
+        } else if self.match_token(TokenType::FunctionDeclaration) {
+            self.function_declaration()
         // } else if self.match_token(TokenType::Fps) {
         //     let token = self.peek();
         //     // self.current_fps += token.fps;
@@ -116,13 +171,17 @@ impl Parser {
             }
             Fps => {
                 self.advance();
-                Ok(Statement::Fps(self.previous()))
+                self.fps_statement()
             }
             FpsEnd => {
                 self.advance();
                 Ok(Statement::FpsEnd(self.previous()))
             }
-            Print => {
+            FpsForever => {
+                self.advance();
+                Ok(Statement::FpsForever(self.previous()))
+            }
+            Print | Println => {
                 self.advance();
                 self.print_statement()
             }
@@ -142,21 +201,59 @@ impl Parser {
                 self.advance();
                 self.while_statement()
             }
+            Return => {
+                self.advance();
+                self.return_statement()
+            }
+            Wait | Yield => {
+                self.advance();
+                self.wait_statement()
+            }
+            Every | At => {
+                self.advance();
+                self.trigger_statement()
+            }
             _ => self.expression_statement(),
         }
     }
 
+    fn fps_statement(&mut self) -> Result<Statement> {
+        let token = self.previous();
+
+        // `#(expr)` is lexed without a literal duration
+        if token.literal.is_some() {
+            return Ok(Statement::Fps { token, duration: None });
+        }
+
+        self.consume(TokenType::OpenParen, "Expected '(' after '#'")?;
+        let duration = self.expression()?;
+        self.consume(TokenType::CloseParen, "Expected ')' after frame duration")?;
+
+        Ok(Statement::Fps {
+            token,
+            duration: Some(duration),
+        })
+    }
+
     fn print_statement(&mut self) -> Result<Statement> {
+        let keyword = self.previous();
         self.consume(TokenType::OpenParen, "Expected '('")?;
-        let mut expressions = self.eval_until(TokenType::CloseParen)?;
 
-        if expressions.len() == 0 {
-            todo!("return error")
+        let mut arguments: Vec<Expr> = vec![];
+        if !self.check_next_token(TokenType::CloseParen) {
+            loop {
+                arguments.push(self.expression()?);
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
         }
 
+        self.consume(TokenType::CloseParen, "Expected ')' after arguments.")?;
         self.consume(TokenType::Semicolon, "Expected ';' after statement")?;
 
-        Ok(Statement::Print(expressions.remove(0)))
+        let newline = keyword.token_type == TokenType::Println;
+        Ok(Statement::Print { keyword, arguments, newline })
     }
 
     fn block_statement(&mut self) -> Result<Statement> {
@@ -194,29 +291,59 @@ impl Parser {
 
         Ok(Statement::If {
             condition: expr,
-            then_block: then_block,
-            else_block: else_block,
+            then_block,
+            else_block,
         })
     }
 
     fn for_statement(&mut self) -> Result<Statement> {
         use TokenType::*;
-        if self.check_next_token(Range) || self.check_next_token(RangeEqual) {
-            let expr = self.expression()?;
+        // the range is only known once evaluated
+        let expr = self.expression()?;
 
-            self.consume(OpenBrace, "Expected '{' after for range")?;
-            let mut for_block: Vec<Statement> = vec![];
-            while !self.check_next_token(CloseBrace) && !self.is_at_end() {
-                for_block.push(self.declaration()?);
-            }
-            self.consume(CloseBrace, "Expected '}' after for block")?;
+        self.consume(OpenBrace, "Expected '{' after for range")?;
+        self.for_depth += 1;
+        let for_block = self.for_body();
+        self.for_depth -= 1;
+        let for_block = for_block?;
+        self.consume(CloseBrace, "Expected '}' after for block")?;
 
-            Ok(Statement::For {
-                expr,
-                for_block: for_block,
-            })
+        Ok(Statement::For {
+            expr,
+            for_block,
+        })
+    }
+
+    fn for_body(&mut self) -> Result<Vec<Statement>> {
+        let mut for_block: Vec<Statement> = vec![];
+        while !self.check_next_token(TokenType::CloseBrace) && !self.is_at_end() {
+            for_block.push(self.declaration()?);
+        }
+        Ok(for_block)
+    }
+
+    /// `every N` or `at N`, optionally naming the handle that cancels it
+    fn trigger_statement(&mut self) -> Result<Statement> {
+        use TokenType::*;
+        let keyword = self.previous();
+        let frame = self.expression()?;
+
+        let handle = if self.match_token(As) {
+            Some(self.consume(Identifer, "Expected handle name after 'as'")?)
         } else {
-            return Err(ParserError::Consume("Expected a Range/RangeEqual after for declaration".to_owned()).into());
+            None
+        };
+
+        self.consume(OpenBrace, &format!("Expected '{{' after {} frame", keyword.lexeme))?;
+        let mut body: Vec<Statement> = vec![];
+        while !self.check_next_token(CloseBrace) && !self.is_at_end() {
+            body.push(self.declaration()?);
+        }
+        self.consume(CloseBrace, &format!("Expected '}}' after {} block", keyword.lexeme))?;
+
+        match keyword.token_type {
+            Every => Ok(Statement::Every { keyword, frames: frame, handle, body }),
+            _ => Ok(Statement::At { keyword, frame, handle, body }),
         }
     }
 
@@ -234,6 +361,80 @@ impl Parser {
         Ok(Statement::While { condition: expr, while_block }) 
     }
 
+    fn return_statement(&mut self) -> Result<Statement> {
+        let keyword = self.previous();
+        if self.function_depth == 0 {
+            return Err(self.error_at(&keyword, ParserError::ReturnOutsideFunction).into());
+        }
+
+        let value = if self.check_next_token(TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expected ';' after return value")?;
+
+        Ok(Statement::Return { keyword, value })
+    }
+
+    fn wait_statement(&mut self) -> Result<Statement> {
+        let keyword = self.previous();
+
+        // a bare `wait` resumes on the next frame, like `yield`
+        let frames = if keyword.token_type == TokenType::Wait && !self.check_next_token(TokenType::Semicolon) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(TokenType::Semicolon, "Expected ';' after statement")?;
+        Ok(Statement::Wait { keyword, frames })
+    }
+
+    fn function_declaration(&mut self) -> Result<Statement> {
+        use TokenType::*;
+        let name = self.consume(Identifer, "Expected function name")?;
+
+        self.consume(OpenParen, "Expected '(' after function name")?;
+        let mut params: Vec<Token> = vec![];
+        if !self.check_next_token(CloseParen) {
+            loop {
+                if params.len() >= 255 {
+                    let token = self.peek();
+                    return Err(self.error_at(&token, ParserError::MaximumArgumentNumber).into());
+                }
+
+                let param = self.consume(Identifer, "Expected parameter name")?;
+                if params.iter().any(|other| other.lexeme == param.lexeme) {
+                    return Err(self.error_at(&param, ParserError::DuplicateParameter(param.lexeme.clone())).into());
+                }
+                params.push(param);
+
+                if !self.match_token(Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(CloseParen, "Expected ')' after parameters")?;
+
+        self.consume(OpenBrace, "Expected '{' before function body")?;
+        self.function_depth += 1;
+        let body = self.function_body();
+        self.function_depth -= 1;
+        let body = body?;
+        self.consume(CloseBrace, "Expected '}' after function body")?;
+
+        Ok(Statement::Function { name, params, body })
+    }
+
+    fn function_body(&mut self) -> Result<Vec<Statement>> {
+        let mut body: Vec<Statement> = vec![];
+        while !self.check_next_token(TokenType::CloseBrace) && !self.is_at_end() {
+            body.push(self.declaration()?);
+        }
+        Ok(body)
+    }
+
     fn expression_statement(&mut self) -> Result<Statement> {
         let expr = self.expression()?;
         self.consume(TokenType::Semicolon, "Expected ';' after statement")?;
@@ -256,22 +457,37 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Result<Expr> {
-        let expr = self.or()?;
+        let expr = self.range()?;
 
         // println!("expr {:?}", expr);
 
         if self.match_token(TokenType::Equal) {
+            let equals = self.previous();
             let val = self.assignment()?;
 
             match expr {
                 Expr::Variable { id } => return Ok(Expr::Assign { id, value: Box::new(val) }),
-                _ => return Err(ParserError::InvalidAssignment.into()),
+                _ => return Err(self.error_at(&equals, ParserError::InvalidAssignment).into()),
             }
         }
 
         Ok(expr)
     }
 
+    fn range(&mut self) -> Result<Expr> {
+        let expr = self.or()?;
+
+        // ranges don't chain, `0..1..2` is not an expression
+        if self.match_tokens(vec![TokenType::Range, TokenType::RangeEqual]) {
+            let operator = self.previous();
+            let right = self.or()?;
+
+            return Ok(Expr::Binary { left: Box::new(expr), operator, right: Box::new(right) });
+        }
+
+        Ok(expr)
+    }
+
     fn or(&mut self) -> Result<Expr> {
         let mut expr = self.and()?;
 
@@ -279,7 +495,7 @@ impl Parser {
             let operator = self.previous();
             let right = self.and()?;
 
-            expr = Expr::Logical { left: Box::new(expr), operator: operator, right: Box::new(right) };
+            expr = Expr::Logical { left: Box::new(expr), operator, right: Box::new(right) };
 
             
         }
@@ -294,7 +510,7 @@ impl Parser {
             let operator = self.previous();
             let right = self.equality()?;
 
-            expr = Expr::Logical { left: Box::new(expr), operator: operator, right: Box::new(right) };
+            expr = Expr::Logical { left: Box::new(expr), operator, right: Box::new(right) };
         }
 
         Ok(expr)
@@ -368,30 +584,33 @@ impl Parser {
         }
     }
 
-    fn eval_until(&mut self, token_type: TokenType) -> Result<Vec<Expr>> {
-        let mut consumed: Vec<Expr> = vec![];
-
-        loop {
-            consumed.push(self.expression()?);
-            if self.is_at_end() {
-                return Err(ParserError::Consume(format!("Expected '{:?}'", token_type).to_string()).into());
-            }
-            if self.peek().token_type == token_type {
-                self.consume(token_type, "Expected ')'")?;
-                break;
-            }
-        }
-
-        Ok(consumed)
-    }
-
     fn consume(&mut self, token_type: TokenType, msg: &str) -> Result<Token> {
         let token = self.peek();
         if token.token_type == token_type {
             self.advance();
             return Ok(self.previous());
         }
-        return Err(ParserError::Consume(msg.to_string()).into());
+        Err(self.error_at(&token, ParserError::Consume(msg.to_string())).expected(token_type.to_string()).into())
+    }
+
+    /// error located at the given token
+    fn error_at(&self, token: &Token, err: ParserError) -> Diagnostic {
+        let found = match token.token_type {
+            TokenType::Eof => "end of input".to_owned(),
+            _ => format!("'{}'", token.lexeme),
+        };
+        Diagnostic::error(err.to_string(), token.span()).found(found)
+    }
+
+    /// locate errors raised without a token, e.g. by the ast, at the token being parsed
+    fn diagnostic(&mut self, err: anyhow::Error) -> Diagnostic {
+        match err.downcast::<Diagnostic>() {
+            Ok(diagnostic) => diagnostic,
+            Err(err) => {
+                let token = self.peek();
+                Diagnostic::error(err.to_string(), token.span())
+            }
+        }
     }
 
     fn primary(&mut self) -> Result<Expr> {
@@ -399,14 +618,12 @@ impl Parser {
 
         let token = self.peek();
         // println!("{}", token);
-        let result = match token.token_type {
-            Number | StringLiteral | True | False | Null => {
+        match token.token_type {
+            StringLiteral if matches!(token.literal, Some(lexer::LiteralValue::Interpolation(_))) => {
                 self.advance();
-                Ok(Expr::Literal {
-                    value: LiteralValue::from_token(token)?,
-                })
+                self.interpolation(token)
             }
-            Range | RangeEqual => {
+            Number | StringLiteral | True | False | Null => {
                 self.advance();
                 Ok(Expr::Literal {
                     value: LiteralValue::from_token(token)?,
@@ -423,22 +640,71 @@ impl Parser {
                 let id = self.previous();
                 Ok(Expr::Variable { id })
             }
+            OpenBracket => {
+                self.advance();
+                let mut elements: Vec<Expr> = vec![];
+                if !self.check_next_token(CloseBracket) {
+                    loop {
+                        elements.push(self.expression()?);
+                        if !self.match_token(Comma) {
+                            break;
+                        }
+                    }
+                }
+                let bracket = self.consume(CloseBracket, "Expected ']' after list elements")?;
+                Ok(Expr::List { elements, bracket })
+            }
             Fps => Ok(Expr::Literal {
                 value: LiteralValue::from_token(token)?,
             }),
             FpsEnd => Ok(Expr::Literal { value: LiteralValue::Null }),
-            Comment => Ok(Expr::Ignore { token: token }),
+            Comment => Ok(Expr::Ignore { token }),
             It => {
                 self.advance();
+                if self.for_depth == 0 {
+                    return Err(self.error_at(&token, ParserError::ItOutsideFor).into());
+                }
                 Ok(Expr::ReservedLiteral { value: token.lexeme })
             }
             _ => {
                 // println!("{:?}", token);
-                return Err(ParserError::ExpectedExpression(token.lexeme.to_owned(), token.line).into());
+                Err(self.error_at(&token, ParserError::ExpectedExpression).into())
             }
+        }
+    }
+
+    /// concatenation of the text and expressions of an interpolated string
+    fn interpolation(&mut self, token: Token) -> Result<Expr> {
+        let parts = match &token.literal {
+            Some(lexer::LiteralValue::Interpolation(parts)) => parts.clone(),
+            _ => vec![],
         };
 
-        result
+        let mut exprs: Vec<Expr> = vec![];
+        for part in parts {
+            match part {
+                StringPart::Text(text) => exprs.push(Expr::Literal {
+                    value: LiteralValue::StringValue(text),
+                }),
+                StringPart::Expr(tokens) => {
+                    // the interpolation is parsed in the context of the string, e.g. inside a for loop
+                    let mut parser = Parser {
+                        tokens,
+                        current: 0,
+                        function_depth: self.function_depth,
+                        for_depth: self.for_depth,
+                    };
+                    exprs.push(parser.expression()?);
+                    if !parser.is_at_end() {
+                        let token = parser.peek();
+                        let message = ParserError::Consume("Expected '}' after interpolated expression".to_owned());
+                        return Err(parser.error_at(&token, message).expected(TokenType::CloseBrace.to_string()).into());
+                    }
+                }
+            }
+        }
+
+        Ok(Expr::Concat { parts: exprs, token })
     }
 
     fn finish_call(&mut self, callee: Expr) -> Result<Expr> {
@@ -451,8 +717,8 @@ impl Parser {
                 arguments.push(arg);
 
                 if arguments.len() >= 255 {
-                    let line: usize = self.peek().line;
-                    return Err(ParserError::MaximumArgumentNumber(line).into())
+                    let token = self.peek();
+                    return Err(self.error_at(&token, ParserError::MaximumArgumentNumber).into())
                 }
 
                 if !self.match_token(Comma) {
@@ -473,6 +739,15 @@ impl Parser {
         loop {
             if self.match_token(OpenParen) {
                 expr = self.finish_call(expr)?;
+            } else if self.match_token(OpenBracket) {
+                let bracket = self.previous();
+                let index = self.expression()?;
+                self.consume(CloseBracket, "Expected ']' after index")?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
+                };
             } else {
                 break;
             }
@@ -504,7 +779,7 @@ impl Parser {
             let rhs = self.unary()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
-                operator: operator,
+                operator,
                 right: Box::new(rhs),
             };
         }
@@ -520,7 +795,7 @@ impl Parser {
             let rhs = self.fac()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
-                operator: operator,
+                operator,
                 right: Box::new(rhs),
             };
         }
@@ -536,7 +811,7 @@ impl Parser {
             let rhs = self.term()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
-                operator: operator,
+                operator,
                 right: Box::new(rhs),
             };
         }
@@ -552,7 +827,7 @@ impl Parser {
             let rhs = self.comparison()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
-                operator: operator,
+                operator,
                 right: Box::new(rhs),
             };
         }
@@ -564,11 +839,12 @@ impl Parser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::diagnostic::Span;
     use crate::lexer::{FpsInput, LiteralValue::*, Token, TokenType::*};
 
     macro_rules! token {
         ($token_type: expr, $lexeme: expr, $literal: expr) => {
-            Token::new($token_type, $lexeme.into(), $literal, 0, 0)
+            Token::new($token_type, $lexeme.into(), $literal, 0, 0, 0)
         };
     }
 
@@ -578,14 +854,14 @@ mod tests {
         let input = vec![
             token!(Number, "4.", Some(Float(4.))),
             token!(Plus, "+", None),
-            token!(Number, "20", Some(Float(20.))),
+            token!(Number, "20", Some(Int(20))),
             token!(Semicolon, ";", None),
         ];
 
         let mut parser = Parser::new(input);
         let expression = parser.expression();
 
-        assert_eq!(expression.unwrap().to_string(), "(+ 4 20)")
+        assert_eq!(expression.unwrap().to_string(), "(+ 4.0 20)")
     }
 
     #[test]
@@ -600,6 +876,84 @@ mod tests {
         assert_eq!(expression.unwrap().to_string(), "(== (+ 4 20) (+ 5 6))")
     }
 
+    #[test]
+    fn test_range() {
+        let input = "-2..n + 1";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let mut parser = Parser::new(scanner.tokens);
+        let expression = parser.expression();
+
+        assert_eq!(expression.unwrap().to_string(), "(.. (- 2) (+ (var n) 1))")
+    }
+
+    #[test]
+    fn test_index() {
+        let input = "split(s, \",\")[0][1..=n]";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let mut parser = Parser::new(scanner.tokens);
+        let expression = parser.expression();
+
+        assert_eq!(expression.unwrap().to_string(), "(index (index (fn (var split) [(var s) ,]) 0) (..= 1 (var n)))")
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let input = r#""fps {a + 1}!""#;
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let mut parser = Parser::new(scanner.tokens);
+        let expression = parser.expression();
+
+        assert_eq!(expression.unwrap().to_string(), "(concat [fps  (+ (var a) 1) !])")
+    }
+
+    #[test]
+    fn test_wait_statement() {
+        let input = "wait 2 * n; wait; yield; ##";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        assert_eq!(
+            statements.iter().map(|x| x.to_string()).collect::<Vec<String>>(),
+            vec!["wait (* 2 (var n));", "wait;", "yield;", "##"]
+        )
+    }
+
+    #[test]
+    fn test_trigger_statements() {
+        let input = "every n + 1 as tick { n = n + 1; } at 120 { print(n); } ##";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        assert_eq!(
+            statements.iter().map(|x| x.to_string()).collect::<Vec<String>>(),
+            vec!["every (+ (var n) 1) as tick { .. }", "at 120 { .. }", "##"]
+        )
+    }
+
+    #[test]
+    fn test_list() {
+        let input = "[1, \"a\" + b, []]";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let mut parser = Parser::new(scanner.tokens);
+        let expression = parser.expression();
+
+        assert_eq!(expression.unwrap().to_string(), "(list [1 (+ a (var b)) (list [])])")
+    }
+
     #[test]
 
     fn test_logical_and() {
@@ -647,14 +1001,26 @@ mod tests {
         let expression = parser.parse();
 
         let expected = vec![
-            Statement::Print(Expr::Literal {
-                value: LiteralValue::Number(1.),
-            }),
+            Statement::Print {
+                keyword: Token {
+                    token_type: Print,
+                    lexeme: "print".to_owned(),
+                    literal: Some(Keyword("print".to_owned())),
+                    line: 1,
+                    start: 0,
+                    pos: 5,
+                },
+                arguments: vec![Expr::Literal {
+                    value: LiteralValue::Int(1),
+                }],
+                newline: false,
+            },
             Statement::FpsEnd(Token {
                 token_type: FpsEnd,
                 lexeme: "##".to_owned(),
                 literal: None,
                 line: 1,
+                start: 10,
                 pos: 12,
             }),
         ];
@@ -679,10 +1045,11 @@ mod tests {
                     lexeme: "a".to_owned(),
                     literal: Some(Identifier("a".to_owned())),
                     line: 1,
+                    start: 4,
                     pos: 5,
                 },
                 expr: Expr::Literal {
-                    value: LiteralValue::Number(1.),
+                    value: LiteralValue::Float(1.),
                 },
             },
             Statement::FpsEnd(Token {
@@ -690,6 +1057,7 @@ mod tests {
                 lexeme: "##".to_owned(),
                 literal: None,
                 line: 1,
+                start: 12,
                 pos: 14,
             }),
         ];
@@ -714,10 +1082,11 @@ mod tests {
                     lexeme: "a".to_owned(),
                     literal: Some(Identifier("a".to_owned())),
                     line: 1,
+                    start: 4,
                     pos: 5,
                 },
                 expr: Expr::Literal {
-                    value: LiteralValue::Number(1.),
+                    value: LiteralValue::Int(1),
                 },
             },
             Statement::ArithmeticExpr(Expr::Assign {
@@ -726,10 +1095,11 @@ mod tests {
                     lexeme: "a".to_string(),
                     literal: Some(Identifier("a".to_string())),
                     line: 1,
+                    start: 10,
                     pos: 11,
                 },
                 value: Box::from(Expr::Literal {
-                    value: LiteralValue::Number(2.),
+                    value: LiteralValue::Int(2),
                 }),
             }),
             Statement::FpsEnd(Token {
@@ -737,6 +1107,7 @@ mod tests {
                 lexeme: "##".to_owned(),
                 literal: None,
                 line: 1,
+                start: 17,
                 pos: 19,
             }),
         ];
@@ -761,30 +1132,183 @@ mod tests {
                     lexeme: "a".to_owned(),
                     literal: Some(Identifier("a".to_owned())),
                     line: 1,
+                    start: 4,
                     pos: 5,
                 },
                 expr: Expr::Literal {
-                    value: LiteralValue::Number(1.),
+                    value: LiteralValue::Int(1),
                 },
             },
-            Statement::Print(Expr::Variable {
-                id: Token {
-                    token_type: Identifer,
-                    lexeme: "a".to_owned(),
-                    literal: Some(Identifier("a".to_owned())),
+            Statement::Print {
+                keyword: Token {
+                    token_type: Print,
+                    lexeme: "print".to_owned(),
+                    literal: Some(Keyword("print".to_owned())),
                     line: 1,
-                    pos: 17,
+                    start: 10,
+                    pos: 15,
                 },
-            }),
+                arguments: vec![Expr::Variable {
+                    id: Token {
+                        token_type: Identifer,
+                        lexeme: "a".to_owned(),
+                        literal: Some(Identifier("a".to_owned())),
+                        line: 1,
+                        start: 16,
+                        pos: 17,
+                    },
+                }],
+                newline: false,
+            },
             Statement::FpsEnd(Token {
                 token_type: FpsEnd,
                 lexeme: "##".to_owned(),
                 literal: None,
                 line: 1,
+                start: 20,
                 pos: 22,
             }),
         ];
 
         assert_eq!(expression.unwrap(), expected)
     }
+
+    #[test]
+    fn function_declaration() {
+        let input = "fn add(a, b) { return a + b; } ##";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        match &statements[0] {
+            Statement::Function { name, params, body } => {
+                assert_eq!(name.lexeme, "add");
+                assert_eq!(params.iter().map(|p| p.lexeme.as_str()).collect::<Vec<&str>>(), vec!["a", "b"]);
+                assert_eq!(body.len(), 1);
+                assert!(matches!(body[0], Statement::Return { .. }));
+            }
+            statement => panic!("expected a function declaration, got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn return_outside_function() {
+        let input = "return 1; ##";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let mut parser = Parser::new(scanner.tokens);
+        let result = parser.parse();
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Cannot return from top-level code"
+        )
+    }
+
+    #[test]
+    fn forever_section() {
+        let input = "print(1); #* print(2);";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse().unwrap();
+
+        assert_eq!(
+            statements.iter().map(|x| x.to_string()).collect::<Vec<String>>(),
+            vec!["print(1);", "#*", "print(2);"]
+        );
+
+        let input = "#* print(1); #2 print(2); ##";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let mut parser = Parser::new(scanner.tokens);
+        let result = parser.parse();
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The open-ended section '#*' must be the last one"
+        )
+    }
+
+    #[test]
+    fn incremental_input() {
+        let input = "let a = 1; print(a);";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        assert!(Parser::new(scanner.tokens.clone()).parse().is_err());
+
+        let mut parser = Parser::new(scanner.tokens);
+        let statements = parser.parse_incremental().unwrap();
+
+        assert_eq!(
+            statements.iter().map(|x| x.to_string()).collect::<Vec<String>>(),
+            vec!["let a = 1;", "print((var a));"]
+        )
+    }
+
+    #[test]
+    fn it_outside_for() {
+        let input = "print(it); ##";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let mut parser = Parser::new(scanner.tokens);
+        let result = parser.parse();
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "'it' can only be used inside a for loop"
+        )
+    }
+
+    #[test]
+    fn missing_semicolon_diagnostic() {
+        let input = "let a = 1 print(a); ##";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let mut parser = Parser::new(scanner.tokens);
+        let result = parser.parse();
+
+        let diagnostics = result.unwrap_err().downcast::<Diagnostics>().unwrap();
+        let diagnostic = &diagnostics.0[0];
+
+        assert_eq!(diagnostics.0.len(), 1);
+        assert_eq!(diagnostic.span, Span::new(10, 15));
+        assert_eq!(diagnostic.expected, Some("Semicolon".to_owned()));
+        assert_eq!(diagnostic.found, Some("'print'".to_owned()));
+    }
+
+    #[test]
+    fn duplicate_parameter() {
+        let input = "fn f(a, b, a) { return a; } ##";
+        let mut scanner = FpsInput::new(input);
+        scanner.scan_tokens().expect("error scanning tokens");
+
+        let diagnostics = Parser::new(scanner.tokens).parse().unwrap_err().downcast::<Diagnostics>().unwrap();
+
+        assert_eq!(diagnostics.0[0].message, "Duplicate parameter 'a'");
+        assert_eq!(diagnostics.0[0].span, Span::new(11, 12));
+    }
+
+    #[test]
+    fn lone_expression() {
+        let parse = |input: &str| {
+            let mut scanner = FpsInput::new(input);
+            scanner.scan_tokens().expect("error scanning tokens");
+            Parser::new(scanner.tokens).parse_expression()
+        };
+
+        assert_eq!(parse("it * 2").unwrap().to_string(), "(* it 2)");
+
+        let diagnostics = parse("a + 1 b").unwrap_err().downcast::<Diagnostics>().unwrap();
+        assert_eq!(diagnostics.0[0].message, "Expected the end of the expression");
+        assert_eq!(diagnostics.0[0].span, Span::new(6, 7));
+    }
 }
@@ -0,0 +1,88 @@
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::collections::BTreeSet;
+
+use super::COMMANDS;
+use crate::lexer::{is_identifier_char, KEYWORDS};
+
+/// Line editor support completing keywords, commands and the names declared in the session
+#[derive(Debug, Default)]
+pub struct FpsHelper {
+    // built-ins and variables of the session, refreshed before every line is read
+    pub names: BTreeSet<String>,
+}
+
+impl Completer for FpsHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(line, pos, &self.names))
+    }
+}
+
+impl Hinter for FpsHelper {
+    type Hint = String;
+}
+
+impl Highlighter for FpsHelper {}
+
+// incomplete inputs are continued by the REPL itself, under its own prompt
+impl Validator for FpsHelper {}
+
+impl Helper for FpsHelper {}
+
+/// start of the word before the cursor and the candidates it could be completed to
+///
+/// A word right after a leading `:` is completed to a command, any other to a keyword or name
+fn complete(line: &str, pos: usize, names: &BTreeSet<String>) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, ch)| is_identifier_char(*ch))
+        .last()
+        .map_or(pos, |(index, _)| index);
+    let word = &line[start..pos];
+
+    let candidates: BTreeSet<&str> = match start == 1 && line.starts_with(':') {
+        true => COMMANDS.iter().copied().collect(),
+        false if word.is_empty() => return (pos, vec![]),
+        false => KEYWORDS.keys().copied().chain(names.iter().map(String::as_str)).collect(),
+    };
+
+    let candidates = candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .map(str::to_owned)
+        .collect();
+    (start, candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_words() {
+        let names = BTreeSet::from(["len".to_owned(), "lower".to_owned(), "level".to_owned()]);
+
+        assert_eq!(complete("le", 2, &names), (0, vec!["len".to_owned(), "let".to_owned(), "level".to_owned()]));
+        assert_eq!(complete("print(lo", 8, &names), (6, vec!["lower".to_owned()]));
+        assert_eq!(complete("whi x", 3, &names), (0, vec!["while".to_owned()]));
+        assert_eq!(complete("let a = ", 8, &names), (8, vec![]));
+        assert_eq!(complete("zz", 2, &names), (0, vec![]));
+        // `_` is not part of an identifier, like in the lexer
+        assert_eq!(complete("x_le", 4, &names), (2, vec!["len".to_owned(), "let".to_owned(), "level".to_owned()]));
+    }
+
+    #[test]
+    fn complete_commands() {
+        let names = BTreeSet::from(["frames".to_owned()]);
+
+        assert_eq!(complete(":fr", 3, &names), (1, vec!["frames".to_owned()]));
+        assert_eq!(complete(":", 1, &names).1.len(), COMMANDS.len());
+        assert_eq!(complete(":step fr", 8, &names), (6, vec!["frames".to_owned()]));
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::Result;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// file in the user's home directory keeping the inputs of past sessions
+const HISTORY_FILE: &str = ".fps_history";
+
+/// Inputs entered in the REPL, persisted one per line so the next session starts with them
+#[derive(Debug)]
+pub struct History {
+    // `None` when there is no home directory, the history then only lasts for the session
+    path: Option<PathBuf>,
+    entries: Vec<String>,
+}
+
+impl History {
+    /// history of the user, read from their home directory
+    pub fn load() -> Self {
+        let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"));
+        match home {
+            Some(home) => Self::open(PathBuf::from(home).join(HISTORY_FILE)),
+            None => Self {
+                path: None,
+                entries: vec![],
+            },
+        }
+    }
+
+    /// history kept in the given file, a missing file is an empty history
+    pub fn open(path: PathBuf) -> Self {
+        let entries = fs::read_to_string(&path)
+            .map(|content| content.lines().map(decode).collect())
+            .unwrap_or_default();
+        Self { path: Some(path), entries }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// record an input and append it to the file, repeating the last input is only recorded once
+    pub fn add(&mut self, entry: &str) -> Result<()> {
+        if self.entries.last().is_some_and(|last| last == entry) {
+            return Ok(());
+        }
+        self.entries.push(entry.to_owned());
+
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", encode(entry))?;
+        }
+        Ok(())
+    }
+}
+
+/// multi-line inputs are kept on a single line by escaping their line breaks
+fn encode(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn decode(line: &str) -> String {
+    let mut decoded = String::new();
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+        match (ch, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                decoded.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                decoded.push('\\');
+                chars.next();
+            }
+            _ => decoded.push(ch),
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_line_breaks() {
+        let entry = "for 0..2 {\n    println(\"a\\nb\");\n}";
+
+        assert_eq!(encode(entry), "for 0..2 {\\n    println(\"a\\\\nb\");\\n}");
+        assert_eq!(decode(&encode(entry)), entry);
+    }
+
+    #[test]
+    fn persisted_entries() {
+        let path = env::temp_dir().join(format!("fps_history_test_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut history = History::open(path.clone());
+        history.add("let a = 1;").unwrap();
+        history.add("let a = 1;").unwrap();
+        history.add("if a {\n    print(a);\n}").unwrap();
+
+        let reloaded = History::open(path.clone());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(reloaded.entries(), ["let a = 1;", "if a {\n    print(a);\n}"]);
+    }
+}
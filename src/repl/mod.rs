@@ -0,0 +1,472 @@
+mod completion;
+mod history;
+
+use anyhow::Result;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Config, Editor};
+use std::fs;
+use std::io::{stdout, Write};
+use thiserror::Error;
+
+use crate::ast::literal::LiteralValue;
+use crate::ast::statement::Statement;
+use crate::diagnostic::{render_error, render_input_error, Diagnostics};
+use crate::interpreter::Interpreter;
+use crate::lexer::{FpsInput, TokenType};
+use crate::parser::Parser;
+use crate::{compile, execute, Cli, Mode};
+use completion::FpsHelper;
+use history::History;
+
+/// name errors of the entered source are reported against
+const NAME: &str = "<repl>";
+
+const PROMPT: &str = "fps> ";
+/// prompt of the lines continuing an incomplete input
+const CONTINUATION: &str = "...> ";
+
+const HELP: &str = ":tokens <src>  list the tokens of the source
+:ast <src>     print the statements parsed from the source
+:frames        list the tasks scheduled on the next frames
+:env           list the variables of the session
+:reset         start a new session
+:load <file>   run a .fps file in the session
+:step [<src>]  run the next frame with scheduled tasks, after scheduling the source if given
+:history       list the inputs of this and past sessions
+:help          print this help
+:quit          end the session, like \\q";
+
+/// names completed after a leading `:`
+const COMMANDS: [&str; 10] = [
+    "tokens", "ast", "frames", "env", "reset", "load", "step", "history", "help", "quit",
+];
+
+#[derive(Error, Debug)]
+enum ReplError {
+    #[error("Unknown command ':{0}', type ':help' for the list of commands")]
+    UnknownCommand(String),
+    #[error("Command ':{0}' expects {1}")]
+    MissingArgument(&'static str, &'static str),
+}
+
+/// Inputs starting with `:` inspect the session instead of running fps source
+#[derive(Debug, PartialEq)]
+enum Command {
+    Tokens(String),
+    Ast(String),
+    Frames,
+    Env,
+    Reset,
+    Load(String),
+    Step(Option<String>),
+    History,
+    Help,
+    Quit,
+}
+
+impl Command {
+    /// command of an input, `None` when it is fps source
+    fn parse(input: &str) -> Result<Option<Command>> {
+        if input == "\\q" {
+            return Ok(Some(Command::Quit));
+        }
+        let Some(command) = input.strip_prefix(':') else {
+            return Ok(None);
+        };
+
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command, ""),
+        };
+        let required = |name: &'static str, expected: &'static str| -> Result<String> {
+            match argument {
+                "" => Err(ReplError::MissingArgument(name, expected).into()),
+                argument => Ok(argument.to_owned()),
+            }
+        };
+
+        let command = match name {
+            "tokens" => Command::Tokens(required("tokens", "fps source")?),
+            "ast" => Command::Ast(required("ast", "fps source")?),
+            "frames" => Command::Frames,
+            "env" => Command::Env,
+            "reset" => Command::Reset,
+            "load" => Command::Load(required("load", "a file path")?),
+            "step" => Command::Step((!argument.is_empty()).then(|| argument.to_owned())),
+            "history" => Command::History,
+            "help" | "h" => Command::Help,
+            "quit" | "q" => Command::Quit,
+            _ => return Err(ReplError::UnknownCommand(name.to_owned()).into()),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// Interactive session, every input continues the program entered so far
+pub struct Repl<'a> {
+    args: &'a Cli,
+    interpreter: Interpreter,
+    // source scheduled by the last `:step`, the errors of the frames it steps through point into it
+    stepped: String,
+    // program the stepped source runs as, errors of other programs are shown without their location
+    stepped_program: usize,
+    history: History,
+    editor: Editor<FpsHelper, DefaultHistory>,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(args: &'a Cli) -> Result<Self> {
+        let history = History::load();
+
+        // inputs are added once complete, so a multi-line input is recalled as a whole
+        let config = Config::builder().auto_add_history(false).history_ignore_dups(true)?.build();
+        let mut editor = Editor::with_config(config)?;
+        editor.set_helper(Some(FpsHelper::default()));
+        for entry in history.entries() {
+            editor.add_history_entry(entry.as_str())?;
+        }
+
+        Ok(Self {
+            args,
+            interpreter: args.interpreter(),
+            stepped: String::new(),
+            stepped_program: 0,
+            history,
+            editor,
+        })
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        println!("# REPL  -  FPS Lang #");
+        println!("--------------------");
+        println!("Type ':help' for the commands, '\\q' to exit");
+        let mut input = String::new();
+        loop {
+            if let Some(helper) = self.editor.helper_mut() {
+                helper.names = self.interpreter.environment.names();
+            }
+            let buffer = match self.editor.readline(if input.is_empty() { PROMPT } else { CONTINUATION }) {
+                Ok(buffer) => buffer,
+                // Ctrl-C drops the input being entered
+                Err(ReadlineError::Interrupted) => {
+                    input.clear();
+                    continue;
+                }
+                // Ctrl-D or end of input
+                Err(ReadlineError::Eof) => break,
+                Err(err) => return Err(err.into()),
+            };
+            let line = buffer.trim_end();
+
+            if input.is_empty() {
+                // an empty input would still take a frame
+                if line.is_empty() {
+                    continue;
+                }
+                input.push_str(line);
+            } else if !line.is_empty() {
+                input.push('\n');
+                input.push_str(line);
+            }
+
+            // an empty line runs an incomplete input anyway, to show what it is missing
+            if !line.is_empty() && !input.starts_with(':') && is_incomplete(&input) {
+                continue;
+            }
+
+            self.editor.add_history_entry(input.as_str())?;
+            if let Err(err) = self.history.add(&input) {
+                eprintln!("warning: could not save the history: {}", err);
+            }
+            let running = self.handle(&input)?;
+            input.clear();
+            if !running {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// run an input, false once the session is over
+    fn handle(&mut self, input: &str) -> Result<bool> {
+        let command = match Command::parse(input) {
+            Ok(Some(command)) => command,
+            Ok(None) => {
+                if let Err(err) = execute(&mut self.interpreter, input, NAME, Mode::Session) {
+                    println!("{}", err);
+                }
+                return Ok(true);
+            }
+            Err(err) => {
+                println!("error: {}", err);
+                return Ok(true);
+            }
+        };
+
+        match command {
+            Command::Tokens(source) => match tokens(&source) {
+                Ok(tokens) => println!("{}", tokens),
+                Err(err) => println!("{}", render_error(&err, &source, NAME)),
+            },
+            Command::Ast(source) => match ast(&source) {
+                Ok(ast) => println!("{}", ast),
+                Err(err) => println!("{}", render_error(&err, &source, NAME)),
+            },
+            Command::Frames => println!("{}", frames(&self.interpreter)),
+            Command::Env => println!("{}", env(&self.interpreter)),
+            Command::Reset => {
+                self.interpreter = self.args.interpreter();
+                self.stepped.clear();
+                self.stepped_program = 0;
+                println!("session reset");
+            }
+            Command::Load(path) => match fs::read_to_string(&path) {
+                Ok(source) => {
+                    if let Err(err) = execute(&mut self.interpreter, &source, &path, Mode::Session) {
+                        println!("{}", err);
+                    }
+                }
+                Err(err) => println!("ERROR: could not read '{}': {}", path, err),
+            },
+            Command::Step(source) => self.step(source)?,
+            Command::History => {
+                for (index, entry) in self.history.entries().iter().enumerate() {
+                    println!("{:>4}  {}", index + 1, entry.replace('\n', "\n      "));
+                }
+            }
+            Command::Help => println!("{}", HELP),
+            Command::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// run a single frame, so a program can be followed frame by frame with `:frames` and `:env`
+    fn step(&mut self, source: Option<String>) -> Result<()> {
+        if let Some(source) = source {
+            let scheduled = match compile(&source, NAME, Mode::Session) {
+                Ok(statements) => self.interpreter.schedule_input(statements),
+                Err(err) => {
+                    println!("{}", err);
+                    return Ok(());
+                }
+            };
+            if let Err(err) = scheduled {
+                println!("{}", render_error(&err, &source, NAME));
+                return Ok(());
+            }
+            self.stepped = source;
+            self.stepped_program = self.interpreter.program();
+        }
+
+        let mut stdout = stdout().lock();
+        match self.interpreter.run_frame(&mut stdout) {
+            Ok(Some(_)) => {}
+            Ok(None) => writeln!(stdout, "no frames scheduled")?,
+            Err(err) => writeln!(stdout, "{}", render_input_error(&err, self.stepped_program, &self.stepped, NAME))?,
+        }
+        Ok(())
+    }
+}
+
+/// whether the input stops in the middle of a statement, so the next line continues it
+///
+/// That is the case for a string left open, unbalanced brackets or a statement missing its `;`
+fn is_incomplete(source: &str) -> bool {
+    let mut scanner = FpsInput::new(source);
+    if scanner.scan_tokens().is_err() {
+        return scanner.is_unterminated();
+    }
+
+    let depth = scanner.tokens.iter().fold(0, |depth, token| match token.token_type {
+        TokenType::OpenParen | TokenType::OpenBrace | TokenType::OpenBracket => depth + 1,
+        TokenType::CloseParen | TokenType::CloseBrace | TokenType::CloseBracket => depth - 1,
+        _ => depth,
+    });
+    if depth > 0 {
+        return true;
+    }
+
+    // the parser reached the end of the input expecting more
+    let end = scanner.tokens.last().map(|token| token.span().start);
+    let mut parser = Parser::new(scanner.tokens);
+    match parser.parse_incremental() {
+        Ok(_) => false,
+        Err(err) => err
+            .downcast_ref::<Diagnostics>()
+            .is_some_and(|diagnostics| diagnostics.0.iter().any(|diagnostic| Some(diagnostic.span.start) == end)),
+    }
+}
+
+/// a line per token with its line number and type
+fn tokens(source: &str) -> Result<String> {
+    let mut scanner = FpsInput::new(source);
+    scanner.scan_tokens()?;
+
+    Ok(scanner
+        .tokens
+        .iter()
+        .map(|token| format!("{:<4}{:<20}{}", token.line, token.token_type.to_string(), token.lexeme))
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+/// statements of the source, with the statements of their blocks indented below them
+fn ast(source: &str) -> Result<String> {
+    let mut scanner = FpsInput::new(source);
+    scanner.scan_tokens()?;
+
+    let mut parser = Parser::new(scanner.tokens);
+    let statements = parser.parse_incremental()?;
+
+    let mut lines: Vec<String> = vec![];
+    write_ast(&mut lines, &statements, 0);
+    Ok(lines.join("\n"))
+}
+
+fn write_ast(lines: &mut Vec<String>, statements: &[Statement], depth: usize) {
+    let indent = "    ".repeat(depth);
+    for statement in statements {
+        lines.push(format!("{}{}", indent, statement));
+        match statement {
+            Statement::If {
+                then_block, else_block, ..
+            } => {
+                write_ast(lines, then_block, depth + 1);
+                if let Some(else_block) = else_block {
+                    lines.push(format!("{}else", indent));
+                    write_ast(lines, else_block, depth + 1);
+                }
+            }
+            Statement::Block { statements: body }
+            | Statement::For { for_block: body, .. }
+            | Statement::While { while_block: body, .. }
+            | Statement::Function { body, .. }
+            | Statement::Every { body, .. }
+            | Statement::At { body, .. } => write_ast(lines, body, depth + 1),
+            _ => {}
+        }
+    }
+}
+
+/// a line per scheduled task, prefixed by its frame
+fn frames(interpreter: &Interpreter) -> String {
+    let lines = interpreter
+        .scheduled()
+        .iter()
+        .flat_map(|(frame, tasks)| tasks.iter().map(move |scheduled| format!("frame {}: {}", frame, scheduled.task)))
+        .collect::<Vec<String>>();
+
+    match lines.is_empty() {
+        true => "no frames scheduled".to_owned(),
+        false => lines.join("\n"),
+    }
+}
+
+/// a line per variable of the session, strings are quoted to tell them apart from other values
+fn env(interpreter: &Interpreter) -> String {
+    let lines = interpreter
+        .environment
+        .variables()
+        .iter()
+        .map(|(name, value)| match value {
+            LiteralValue::StringValue(val) => format!("{} = {:?}", name, val),
+            _ => format!("{} = {}", name, value),
+        })
+        .collect::<Vec<String>>();
+
+    match lines.is_empty() {
+        true => "no variables declared".to_owned(),
+        false => lines.join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        let inputs = vec![
+            ("let a = 1;", None),
+            ("\\q", Some(Command::Quit)),
+            (":tokens let a", Some(Command::Tokens("let a".to_owned()))),
+            (":load  src/sample.fps ", Some(Command::Load("src/sample.fps".to_owned()))),
+            (":step", Some(Command::Step(None))),
+            (":step print(1);", Some(Command::Step(Some("print(1);".to_owned())))),
+            (":env", Some(Command::Env)),
+        ];
+
+        for (input, expected) in inputs {
+            assert_eq!(Command::parse(input).unwrap(), expected);
+        }
+
+        assert_eq!(
+            Command::parse(":ast").unwrap_err().to_string(),
+            "Command ':ast' expects fps source"
+        );
+        assert_eq!(
+            Command::parse(":run").unwrap_err().to_string(),
+            "Unknown command ':run', type ':help' for the list of commands"
+        );
+    }
+
+    #[test]
+    fn incomplete_inputs() {
+        let inputs = vec![
+            ("let a = 1;", false),
+            ("for 0..3 {", true),
+            ("for 0..3 {\n    println(it);\n}", false),
+            ("print(len(\"a\"", true),
+            ("let a = [1,", true),
+            ("let a = 1", true),
+            ("print(\"not closed", true),
+            ("print(\"not closed\nyet\");", false),
+            ("let = 1;", false),
+            ("}", false),
+        ];
+
+        for (input, expected) in inputs {
+            assert_eq!(is_incomplete(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn print_tokens() {
+        let expected = "1   Declaration         let\n1   Identifer           a\n1   Semicolon           ;\n1   Eof                 ";
+
+        assert_eq!(tokens("let a;").unwrap(), expected);
+    }
+
+    #[test]
+    fn print_ast() {
+        let input = "fn f(n) { if n { print(1); } else { print(2); } } f(1 + 2);";
+        let expected = "fn f(n) { .. }
+    if (var n) { .. } else { .. }
+        print(1);
+    else
+        print(2);
+(fn (var f) [(+ 1 2)]);";
+
+        assert_eq!(ast(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn session_state() {
+        let mut stdout = Vec::new();
+
+        let mut scanner = FpsInput::new("let s = \"a\"; every 2 { print(s); } for 0..2 { print(it); }");
+        scanner.scan_tokens().unwrap();
+        let statements = Parser::new(scanner.tokens).parse_incremental().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.schedule_input(statements).unwrap();
+        interpreter.run_frame(&mut stdout).unwrap();
+
+        assert_eq!(env(&interpreter), "s = \"a\"");
+        assert_eq!(
+            frames(&interpreter),
+            "frame 2: iteration 1: print(it);\nframe 3: every 2 <timer 1>: print((var s));"
+        );
+    }
+}